@@ -2,83 +2,45 @@ use bevy::{
     audio::{AddAudioSource, Source},
     prelude::*,
 };
-use crossbeam_queue::ArrayQueue;
 
-use crate::{ControlEvent, EmuContext, SharedEmuContext};
+use crate::emu::{AudioQueue, SAMPLE_RATE};
 
 pub struct AudioRunnerPlugin {
-    pub(crate) emu: SharedEmuContext,
+    pub(crate) queue: AudioQueue,
 }
 
 impl Plugin for AudioRunnerPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(SharedEmuContextRes(self.emu.clone()))
+        app.insert_resource(AudioQueueRes(self.queue.clone()))
             .add_audio_source::<EmuAudio>()
             .add_systems(Startup, setup);
     }
 }
 
 #[derive(Resource)]
-struct SharedEmuContextRes(SharedEmuContext);
+struct AudioQueueRes(AudioQueue);
 
 #[derive(Asset, TypePath)]
 struct EmuAudio {
-    emu: SharedEmuContext,
-    buf_count: usize,
+    queue: AudioQueue,
     sample_rate: u32,
 }
 
+/// plays what the emulation thread pushed into the queue
 struct AudioRunnder {
-    emu: SharedEmuContext,
+    queue: AudioQueue,
     sample_rate: u32,
-    queue_size: usize,
-    queue_buf: Box<[i16]>,
-    queue: ArrayQueue<i16>,
+    last_sample: i16,
 }
 
 impl AudioRunnder {
     const CHANNELS: usize = 1;
 
-    fn new(emu: SharedEmuContext, queue_size: usize, sample_rate: u32) -> Self {
+    fn new(queue: AudioQueue, sample_rate: u32) -> Self {
         AudioRunnder {
-            emu,
+            queue,
             sample_rate,
-            queue_size,
-            queue_buf: vec![0i16; queue_size].into_boxed_slice(),
-            queue: ArrayQueue::new(queue_size),
-        }
-    }
-}
-
-impl AudioRunnder {
-    fn poll_events(emu: &mut EmuContext) {
-        let EmuContext {
-            bus,
-            cpu,
-            pause,
-            step,
-            cnotrol_events,
-        } = &mut *emu;
-        while let Ok(ev) = cnotrol_events.try_recv() {
-            match ev {
-                ControlEvent::LoadCart(data) => {
-                    if let Some(cart) = les_nes::Cartridge::load(&data) {
-                        bus.load_cart(cart);
-                        bus.reset(cpu);
-                    }
-                }
-                ControlEvent::AudioCtrl(states) => bus.set_audio_control(&states),
-                ControlEvent::Inputs(p0, p1) => {
-                    bus.set_input0(p0);
-                    bus.set_input1(p1);
-                }
-                ControlEvent::Reset => bus.reset(cpu),
-                ControlEvent::Pause => *pause = !*pause,
-                ControlEvent::Step => {
-                    *pause = true;
-                    *step = true;
-                }
-            }
+            last_sample: 0,
         }
     }
 }
@@ -87,45 +49,15 @@ impl Iterator for AudioRunnder {
     type Item = i16;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut emu = self.emu.lock().unwrap();
-        Self::poll_events(&mut emu);
-
-        let EmuContext {
-            bus,
-            cpu,
-            pause,
-            step,
-            ..
-        } = &mut *emu;
-
-        if self.queue.is_empty() {
-            if *pause {
-                if *step {
-                    bus.exec(cpu);
-                    *step = false;
-                }
-
-                return Some(0);
-            } else {
-                let sample_len = self.queue_size / Self::CHANNELS;
-                let needed_cycles = bus.resampler().clocks_needed(sample_len);
-                let cycles = bus.cycles() + needed_cycles;
-                while bus.cycles() < cycles {
-                    bus.exec(cpu);
-                }
-
-                bus.resampler().end_frame();
-                bus.resampler()
-                    .read_samples(&mut self.queue_buf[..sample_len]);
-                self.queue_buf[..sample_len].iter().for_each(|s| {
-                    let _ = self.queue.push(*s);
-                });
-            }
+        // hold the last sample on underrun, dropping to zero would click
+        if let Some(s) = self.queue.pop() {
+            self.last_sample = s;
         }
 
-        self.queue.pop()
+        Some(self.last_sample)
     }
 }
+
 impl Source for AudioRunnder {
     fn current_frame_len(&self) -> Option<usize> {
         None
@@ -150,28 +82,13 @@ impl Decodable for EmuAudio {
     type Decoder = AudioRunnder;
 
     fn decoder(&self) -> Self::Decoder {
-        AudioRunnder::new(self.emu.clone(), self.buf_count, self.sample_rate)
+        AudioRunnder::new(self.queue.clone(), self.sample_rate)
     }
 }
 
-fn setup(
-    mut assets: ResMut<Assets<EmuAudio>>,
-    emu: Res<SharedEmuContextRes>,
-    mut commands: Commands,
-) {
-    const SAMPLE_RATE: u32 = 44_100;
-    const BUF_COUNT: usize = 256;
-
-    emu.0
-        .lock()
-        .unwrap()
-        .bus
-        .resampler()
-        .set_rates(SAMPLE_RATE as _);
-
+fn setup(mut assets: ResMut<Assets<EmuAudio>>, queue: Res<AudioQueueRes>, mut commands: Commands) {
     commands.spawn(AudioPlayer(assets.add(EmuAudio {
-        emu: emu.0.clone(),
-        buf_count: BUF_COUNT,
+        queue: queue.0.clone(),
         sample_rate: SAMPLE_RATE,
     })));
 }
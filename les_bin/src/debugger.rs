@@ -0,0 +1,321 @@
+use bevy_egui::egui;
+use les_nes::{
+    cpu::{CpuStatus, Instruction},
+    debug::{AddrSpace, BreakReason, Breakpoint, Condition, Watchpoint},
+};
+
+use crate::{ControlEvent, ControlSender};
+
+/// editable copy of the debugger settings, sent to the emulation on change
+pub struct DebuggerUi {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    break_on_nmi: bool,
+    break_on_irq: bool,
+
+    bp_addr: String,
+    bp_cond: String,
+    wp_space: AddrSpace,
+    wp_start: String,
+    wp_end: String,
+    wp_access: [bool; 3],
+    run_to: String,
+    error: Option<String>,
+    /// the listing scrolls back to the pc when it moves
+    listing_pc: Option<u16>,
+}
+
+impl Default for DebuggerUi {
+    fn default() -> Self {
+        Self {
+            breakpoints: vec![],
+            watchpoints: vec![],
+            break_on_nmi: false,
+            break_on_irq: false,
+
+            bp_addr: String::new(),
+            bp_cond: String::new(),
+            wp_space: AddrSpace::Cpu,
+            wp_start: String::new(),
+            wp_end: String::new(),
+            wp_access: [false, true, false],
+            run_to: String::new(),
+            error: None,
+            listing_pc: None,
+        }
+    }
+}
+
+/// hex, optionally prefixed with `$` or `0x`
+pub fn parse_addr(s: &str) -> Result<u16, String> {
+    let s = s.trim();
+    let hex = s
+        .strip_prefix('$')
+        .or_else(|| s.strip_prefix("0x"))
+        .unwrap_or(s);
+    u16::from_str_radix(hex, 16).map_err(|_| format!("invalid address `{}`", s))
+}
+
+pub fn debugger_window(
+    ctx: &egui::Context,
+    state: &mut DebuggerUi,
+    cpu: Option<&CpuStatus>,
+    cycles: usize,
+    halted: Option<&BreakReason>,
+    sender: &ControlSender,
+) {
+    egui::Window::new("CPU").resizable(false).show(ctx, |ui| {
+        if let Some(s) = cpu {
+            ui.label(format!("A: {:02X} X: {:02X} Y: {:02X}", s.a, s.x, s.y));
+            ui.label(format!("PC: {:04X} SP: {:04X}", s.pc, s.sp));
+            ui.label(format!("P: {:?}  {:02X}", s.p, s.p.to_u8()));
+            ui.label(format!("CYCLES: {}", cycles));
+        }
+        if let Some(reason) = halted {
+            ui.label(format!("BREAK: {}", reason));
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("RESET").clicked() {
+                let _ = sender.send(ControlEvent::Reset);
+            }
+            if ui.button("STEP").clicked() {
+                let _ = sender.send(ControlEvent::Step);
+            }
+            if ui.button("OVER").clicked() {
+                let _ = sender.send(ControlEvent::StepOver);
+            }
+            if ui.button("OUT").clicked() {
+                let _ = sender.send(ControlEvent::StepOut);
+            }
+            if ui.button("CONTINUE").clicked() {
+                let _ = sender.send(ControlEvent::Pause);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut state.run_to).desired_width(48.0));
+            if ui.button("run to").clicked() {
+                match parse_addr(&state.run_to) {
+                    Ok(addr) => {
+                        let _ = sender.send(ControlEvent::RunTo(addr));
+                    }
+                    Err(e) => state.error = Some(e),
+                }
+            }
+        });
+
+        ui.separator();
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            changed |= ui
+                .checkbox(&mut state.break_on_nmi, "break on NMI")
+                .changed();
+            changed |= ui
+                .checkbox(&mut state.break_on_irq, "break on IRQ")
+                .changed();
+        });
+        if changed {
+            let _ = sender.send(ControlEvent::BreakOnInterrupts {
+                nmi: state.break_on_nmi,
+                irq: state.break_on_irq,
+            });
+        }
+
+        ui.separator();
+        if breakpoints_ui(ui, state) {
+            let _ = sender.send(ControlEvent::Breakpoints(state.breakpoints.clone()));
+        }
+
+        ui.separator();
+        if watchpoints_ui(ui, state) {
+            let _ = sender.send(ControlEvent::Watchpoints(state.watchpoints.clone()));
+        }
+
+        if let Some(e) = &state.error {
+            ui.colored_label(egui::Color32::RED, e.as_str());
+        }
+    });
+}
+
+/// listing around the pc, click the margin to toggle a breakpoint
+pub fn disassembly_window(
+    ctx: &egui::Context,
+    state: &mut DebuggerUi,
+    listing: &[Instruction],
+    pc: Option<u16>,
+    sender: &ControlSender,
+) {
+    let follow = state.listing_pc != pc;
+    state.listing_pc = pc;
+
+    egui::Window::new("Disassembly")
+        .default_height(320.0)
+        .show(ctx, |ui| {
+            let mut toggled = None;
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for ins in listing {
+                    ui.horizontal(|ui| {
+                        let has_bp = state.breakpoints.iter().any(|b| b.pc == ins.addr);
+                        let marker = egui::RichText::new(if has_bp { "●" } else { " " })
+                            .monospace()
+                            .color(egui::Color32::RED);
+                        if ui
+                            .add(egui::Label::new(marker).sense(egui::Sense::click()))
+                            .clicked()
+                        {
+                            toggled = Some(ins.addr);
+                        }
+
+                        let is_pc = Some(ins.addr) == pc;
+                        let text = egui::RichText::new(ins.to_string()).monospace();
+                        let response = ui.selectable_label(is_pc, text);
+                        if response.clicked() {
+                            toggled = Some(ins.addr);
+                        }
+                        if is_pc && follow {
+                            response.scroll_to_me(Some(egui::Align::Center));
+                        }
+                    });
+                }
+            });
+
+            if let Some(addr) = toggled {
+                if let Some(i) = state.breakpoints.iter().position(|b| b.pc == addr) {
+                    state.breakpoints.remove(i);
+                } else {
+                    state.breakpoints.push(Breakpoint {
+                        pc: addr,
+                        condition: None,
+                        enabled: true,
+                    });
+                }
+                let _ = sender.send(ControlEvent::Breakpoints(state.breakpoints.clone()));
+            }
+        });
+}
+
+/// true if the breakpoints changed
+fn breakpoints_ui(ui: &mut egui::Ui, state: &mut DebuggerUi) -> bool {
+    let mut changed = false;
+
+    ui.label("breakpoints");
+    let mut remove = None;
+    for (i, b) in state.breakpoints.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            changed |= ui
+                .checkbox(&mut b.enabled, format!("${:04X}", b.pc))
+                .changed();
+            if let Some(c) = &b.condition {
+                ui.label(c.to_string());
+            }
+            if ui.small_button("x").clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(i) = remove {
+        state.breakpoints.remove(i);
+        changed = true;
+    }
+
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(&mut state.bp_addr).desired_width(48.0));
+        ui.add(
+            egui::TextEdit::singleline(&mut state.bp_cond)
+                .hint_text("a == $10")
+                .desired_width(80.0),
+        );
+        if ui.button("add").clicked() {
+            let condition = match state.bp_cond.trim() {
+                "" => Ok(None),
+                s => s.parse::<Condition>().map(Some),
+            };
+            match (parse_addr(&state.bp_addr), condition) {
+                (Ok(pc), Ok(condition)) => {
+                    state.breakpoints.push(Breakpoint {
+                        pc,
+                        condition,
+                        enabled: true,
+                    });
+                    state.error = None;
+                    changed = true;
+                }
+                (Err(e), _) | (_, Err(e)) => state.error = Some(e),
+            }
+        }
+    });
+
+    changed
+}
+
+/// true if the watchpoints changed
+fn watchpoints_ui(ui: &mut egui::Ui, state: &mut DebuggerUi) -> bool {
+    let mut changed = false;
+
+    ui.label("watchpoints");
+    let mut remove = None;
+    for (i, w) in state.watchpoints.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            let label = format!(
+                "{:?} ${:04X}-${:04X} {}{}{}",
+                w.space,
+                w.range.start(),
+                w.range.end(),
+                if w.read { "r" } else { "-" },
+                if w.write { "w" } else { "-" },
+                if w.exec { "x" } else { "-" },
+            );
+            changed |= ui.checkbox(&mut w.enabled, label).changed();
+            if ui.small_button("x").clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(i) = remove {
+        state.watchpoints.remove(i);
+        changed = true;
+    }
+
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt("wp_space")
+            .selected_text(format!("{:?}", state.wp_space))
+            .width(48.0)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut state.wp_space, AddrSpace::Cpu, "Cpu");
+                ui.selectable_value(&mut state.wp_space, AddrSpace::Ppu, "Ppu");
+            });
+        ui.add(egui::TextEdit::singleline(&mut state.wp_start).desired_width(48.0));
+        ui.add(
+            egui::TextEdit::singleline(&mut state.wp_end)
+                .hint_text("end")
+                .desired_width(48.0),
+        );
+        for (value, name) in state.wp_access.iter_mut().zip(["r", "w", "x"]) {
+            ui.checkbox(value, name);
+        }
+        if ui.button("add").clicked() {
+            let end = match state.wp_end.trim() {
+                "" => parse_addr(&state.wp_start),
+                s => parse_addr(s),
+            };
+            match (parse_addr(&state.wp_start), end) {
+                (Ok(start), Ok(end)) => {
+                    let [read, write, exec] = state.wp_access;
+                    state.watchpoints.push(Watchpoint {
+                        space: state.wp_space,
+                        range: start.min(end)..=start.max(end),
+                        read,
+                        write,
+                        exec,
+                        enabled: true,
+                    });
+                    state.error = None;
+                    changed = true;
+                }
+                (Err(e), _) | (_, Err(e)) => state.error = Some(e),
+            }
+        }
+    });
+
+    changed
+}
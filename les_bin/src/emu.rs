@@ -0,0 +1,219 @@
+use std::{sync::Arc, time::Duration};
+
+use bevy::prelude::*;
+use crossbeam_queue::ArrayQueue;
+
+use crate::{sram, ControlEvent, EmuContext, SharedEmuContext};
+
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// samples buffered between the emulation and the audio device, ~90ms
+pub const AUDIO_QUEUE_SIZE: usize = 4096;
+
+/// rgb pixels of a frame
+pub const FRAME_SIZE: usize = 256 * 240 * 3;
+
+/// how far the resampling rate may drift to keep the audio queue half full
+const MAX_RATE_DELTA: f64 = 0.005;
+
+/// cpu cycles of a ntsc frame
+const FRAME_CYCLES: f64 = 29780.5;
+
+pub type AudioQueue = Arc<ArrayQueue<i16>>;
+pub type FrameInput = triple_buffer::Input<Vec<u8>>;
+pub type FrameOutput = triple_buffer::Output<Vec<u8>>;
+
+/// latest finished frame, for the ui
+#[derive(Resource)]
+pub struct FrameOutputRes(pub FrameOutput);
+
+fn frame_time() -> Duration {
+    Duration::from_secs_f64(FRAME_CYCLES / les_nes::CPU_FREQUENCY as f64)
+}
+
+/// drives the emulation one frame at a time, feeding the audio queue and frame buffer
+#[cfg_attr(target_arch = "wasm32", derive(Resource))]
+pub struct EmuRunner {
+    emu: SharedEmuContext,
+    audio: AudioQueue,
+    frame: FrameInput,
+}
+
+impl EmuRunner {
+    pub fn new(emu: SharedEmuContext, audio: AudioQueue, frame: FrameInput) -> Self {
+        Self { emu, audio, frame }
+    }
+
+    /// run a frame, or a single instruction when stepping while paused
+    fn run_frame(&mut self) {
+        let mut emu = self.emu.lock().unwrap();
+        Self::poll_events(&mut emu);
+
+        let EmuContext {
+            bus,
+            cpu,
+            pause,
+            step,
+            ..
+        } = &mut *emu;
+
+        let pixels = if *pause {
+            if !*step {
+                return;
+            }
+
+            bus.debugger_mut().resume();
+            bus.exec(cpu);
+            *step = false;
+            bus.ppu().display_buf()
+        } else {
+            // produce a bit less audio when the queue fills up and a bit more when it drains
+            let fill = self.audio.len() as f64 / self.audio.capacity() as f64;
+            let rate = SAMPLE_RATE as f64 * (1.0 + MAX_RATE_DELTA * (1.0 - 2.0 * fill));
+            bus.resampler().set_rates(rate);
+
+            let frame = bus.run_frame(cpu);
+            for &s in frame.samples {
+                let _ = self.audio.push(s);
+            }
+
+            // a breakpoint was hit, stay there until continued
+            *pause |= bus.debugger().halted().is_some();
+            bus.ppu().display_buf()
+        };
+
+        self.frame.input_buffer().copy_from_slice(pixels);
+        self.frame.publish();
+    }
+
+    fn poll_events(emu: &mut EmuContext) {
+        while let Ok(ev) = emu.cnotrol_events.try_recv() {
+            match ev {
+                ControlEvent::LoadCart { data, save_key } => {
+                    match les_nes::Cartridge::load(&data) {
+                        Ok(cart) => {
+                            sram::flush(emu);
+
+                            emu.bus.load_cart(cart);
+                            emu.bus.reset(&mut emu.cpu);
+                            emu.save_key = Some(save_key);
+                            emu.cart_loaded = true;
+
+                            sram::restore(emu);
+                        }
+                        Err(e) => emu.message = Some(format!("failed to load rom: {}", e)),
+                    }
+                }
+                ControlEvent::AudioCtrl(states) => emu.bus.set_audio_control(&states),
+                ControlEvent::ExpansionAudioCtrl(states) => {
+                    emu.bus.set_expansion_audio_control(&states)
+                }
+                ControlEvent::Inputs(p0, p1) => {
+                    emu.bus.set_input0(p0);
+                    emu.bus.set_input1(p1);
+                }
+                ControlEvent::Reset => emu.bus.reset(&mut emu.cpu),
+                ControlEvent::Pause => {
+                    emu.pause = !emu.pause;
+                    if !emu.pause {
+                        emu.bus.debugger_mut().resume();
+                    }
+                }
+                ControlEvent::Step => {
+                    emu.pause = true;
+                    emu.step = true;
+                }
+                ControlEvent::StepOver => {
+                    emu.bus.step_over(&mut emu.cpu);
+                    emu.pause = false;
+                }
+                ControlEvent::StepOut => {
+                    emu.bus.step_out(&emu.cpu);
+                    emu.pause = false;
+                }
+                ControlEvent::RunTo(addr) => {
+                    emu.bus.run_to(addr);
+                    emu.pause = false;
+                }
+                ControlEvent::Breakpoints(breakpoints) => {
+                    emu.bus.debugger_mut().breakpoints = breakpoints
+                }
+                ControlEvent::Watchpoints(watchpoints) => {
+                    emu.bus.debugger_mut().watchpoints = watchpoints
+                }
+                ControlEvent::BreakOnInterrupts { nmi, irq } => {
+                    let debugger = emu.bus.debugger_mut();
+                    debugger.break_on_nmi = nmi;
+                    debugger.break_on_irq = irq;
+                }
+                ControlEvent::StartTrace(tracer) => emu.bus.start_trace(tracer),
+                ControlEvent::StopTrace => {
+                    if let Some(Err(e)) = emu.bus.stop_trace() {
+                        emu.message = Some(format!("trace failed: {}", e));
+                    }
+                }
+                ControlEvent::SaveState => emu.state = Some(emu.bus.save_state(&emu.cpu)),
+                ControlEvent::LoadState => {
+                    if let Some(data) = &emu.state {
+                        if let Err(e) = emu.bus.load_state(&mut emu.cpu, data) {
+                            emu.message = Some(format!("failed to load state: {}", e));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// run the emulation on its own thread, paced by the frame time
+#[cfg(not(target_arch = "wasm32"))]
+pub fn start(_app: &mut App, mut runner: EmuRunner) {
+    use std::time::Instant;
+
+    std::thread::Builder::new()
+        .name("emulation".to_owned())
+        .spawn(move || {
+            let frame_time = frame_time();
+            let mut deadline = Instant::now();
+
+            loop {
+                runner.run_frame();
+
+                deadline += frame_time;
+                let now = Instant::now();
+                if deadline > now {
+                    std::thread::sleep(deadline - now);
+                } else if now - deadline > frame_time * 4 {
+                    // too far behind, don't try to catch up
+                    deadline = now;
+                }
+            }
+        })
+        .expect("failed to spawn emulation thread");
+}
+
+/// no threads on the web, run frames from the app update instead
+#[cfg(target_arch = "wasm32")]
+pub fn start(app: &mut App, runner: EmuRunner) {
+    app.insert_resource(runner).add_systems(Update, run_frames);
+}
+
+#[cfg(target_arch = "wasm32")]
+fn run_frames(time: Res<Time>, mut runner: ResMut<EmuRunner>, mut lag: Local<Duration>) {
+    const MAX_FRAMES: usize = 4;
+
+    let frame_time = frame_time();
+    *lag += time.delta();
+
+    let mut frames = 0;
+    while *lag >= frame_time {
+        if frames == MAX_FRAMES {
+            *lag = Duration::ZERO;
+            break;
+        }
+
+        runner.run_frame();
+        *lag -= frame_time;
+        frames += 1;
+    }
+}
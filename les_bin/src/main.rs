@@ -1,20 +1,40 @@
 use bevy::prelude::*;
-use les_nes::{Bus, Cartridge, Cpu, InputStates};
+use crossbeam_queue::ArrayQueue;
+use les_nes::{
+    debug::{Breakpoint, Watchpoint},
+    Bus, Cartridge, Cpu, InputStates, Tracer,
+};
 use std::sync::{Arc, Mutex};
 
 mod audio;
+mod debugger;
+mod emu;
+mod sram;
+#[cfg(not(target_arch = "wasm32"))]
+mod trace;
 mod ui;
 
 type ControlReceiver = crossbeam_channel::Receiver<ControlEvent>;
 type ControlSender = crossbeam_channel::Sender<ControlEvent>;
 
 enum ControlEvent {
-    LoadCart(Vec<u8>),
+    LoadCart { data: Vec<u8>, save_key: String },
     AudioCtrl([bool; 5]),
+    ExpansionAudioCtrl(Vec<bool>),
     Inputs(InputStates, InputStates),
     Reset,
     Pause,
     Step,
+    StepOver,
+    StepOut,
+    RunTo(u16),
+    Breakpoints(Vec<Breakpoint>),
+    Watchpoints(Vec<Watchpoint>),
+    BreakOnInterrupts { nmi: bool, irq: bool },
+    SaveState,
+    LoadState,
+    StartTrace(Tracer),
+    StopTrace,
 }
 
 struct EmuContext {
@@ -22,6 +42,11 @@ struct EmuContext {
     pub bus: Bus,
     pub pause: bool,
     pub step: bool,
+    pub state: Option<Vec<u8>>,
+    pub message: Option<String>,
+    pub save_key: Option<String>,
+    /// a cartridge was loaded since the ui last looked
+    pub cart_loaded: bool,
     pub cnotrol_events: ControlReceiver,
 }
 
@@ -42,10 +67,18 @@ fn main() {
             bus,
             pause: false,
             step: false,
+            state: None,
+            message: None,
+            save_key: None,
+            cart_loaded: false,
             cnotrol_events: receiver,
         }))
     };
 
+    let audio_queue = Arc::new(ArrayQueue::new(emu::AUDIO_QUEUE_SIZE));
+    let (frame_input, frame_output) = triple_buffer::triple_buffer(&vec![0u8; emu::FRAME_SIZE]);
+    let runner = emu::EmuRunner::new(emu.clone(), audio_queue.clone(), frame_input);
+
     let mut app = App::new();
     app.add_plugins(DefaultPlugins.set(WindowPlugin {
         primary_window: Some(Window {
@@ -62,6 +95,10 @@ fn main() {
         emu: emu.clone(),
         control_sender: sender,
     })
-    .add_plugins(audio::AudioRunnerPlugin { emu })
-    .run();
+    .add_plugins(sram::SramPlugin { emu })
+    .add_plugins(audio::AudioRunnerPlugin { queue: audio_queue })
+    .insert_resource(emu::FrameOutputRes(frame_output));
+
+    emu::start(&mut app, runner);
+    app.run();
 }
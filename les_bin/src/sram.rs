@@ -0,0 +1,121 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{EmuContext, SharedEmuContext};
+
+/// battery ram is written back at most this often while playing
+const FLUSH_INTERVAL: f32 = 2.0;
+
+pub struct SramPlugin {
+    pub(crate) emu: SharedEmuContext,
+}
+
+impl Plugin for SramPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SharedEmuContextRes(self.emu.clone()))
+            .insert_resource(FlushTimer(Timer::from_seconds(
+                FLUSH_INTERVAL,
+                TimerMode::Repeating,
+            )))
+            .add_systems(Update, flush_periodically)
+            .add_systems(Last, flush_on_exit);
+    }
+}
+
+#[derive(Resource)]
+struct SharedEmuContextRes(SharedEmuContext);
+
+#[derive(Resource)]
+struct FlushTimer(Timer);
+
+fn flush_periodically(
+    time: Res<Time>,
+    mut timer: ResMut<FlushTimer>,
+    emu: Res<SharedEmuContextRes>,
+) {
+    if timer.0.tick(time.delta()).just_finished() {
+        flush(&mut emu.0.lock().unwrap());
+    }
+}
+
+fn flush_on_exit(mut exit: MessageReader<AppExit>, emu: Res<SharedEmuContextRes>) {
+    if exit.read().next().is_some() {
+        flush(&mut emu.0.lock().unwrap());
+    }
+}
+
+/// storage key of the battery save for a picked rom
+pub(crate) fn save_key(handle: &rfd::FileHandle) -> String {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        handle
+            .path()
+            .with_extension("sav")
+            .to_string_lossy()
+            .into_owned()
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        format!("les.sav.{}", handle.file_name())
+    }
+}
+
+/// load the battery save of the current cartridge, if there is one
+pub(crate) fn restore(emu: &mut EmuContext) {
+    let EmuContext { bus, save_key, .. } = emu;
+    if let Some(key) = save_key {
+        if bus.cart().sram().is_some() {
+            if let Some(data) = storage::read(key) {
+                bus.cart_mut().load_sram(&data);
+            }
+        }
+    }
+}
+
+/// write the battery save of the current cartridge if it changed
+pub(crate) fn flush(emu: &mut EmuContext) {
+    let EmuContext { bus, save_key, .. } = emu;
+    if let Some(key) = save_key {
+        let cart = bus.cart_mut();
+        if cart.poll_sram_dirty() {
+            if let Some(data) = cart.sram() {
+                storage::write(key, data);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod storage {
+    pub fn read(key: &str) -> Option<Vec<u8>> {
+        std::fs::read(key).ok()
+    }
+
+    pub fn write(key: &str, data: &[u8]) {
+        if let Err(e) = std::fs::write(key, data) {
+            eprintln!("failed to write {}: {}", key, e);
+        }
+    }
+}
+
+/// localStorage only holds strings, saves are stored as hex
+#[cfg(target_arch = "wasm32")]
+mod storage {
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    pub fn read(key: &str) -> Option<Vec<u8>> {
+        let s = local_storage()?.get_item(key).ok()??;
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+
+    pub fn write(key: &str, data: &[u8]) {
+        let s: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+        if let Some(storage) = local_storage() {
+            let _ = storage.set_item(key, &s);
+        }
+    }
+}
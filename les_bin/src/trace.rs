@@ -0,0 +1,123 @@
+use bevy_egui::egui;
+use les_nes::{TraceFilter, Tracer};
+
+use crate::{ControlEvent, ControlSender};
+
+/// settings of the next trace, the filters are optional
+pub struct TraceUi {
+    path: String,
+    start: String,
+    end: String,
+    bank: String,
+    nmi_only: bool,
+    /// in MB
+    limit: String,
+    error: Option<String>,
+}
+
+impl Default for TraceUi {
+    fn default() -> Self {
+        Self {
+            path: "trace.log".to_owned(),
+            start: String::new(),
+            end: String::new(),
+            bank: String::new(),
+            nmi_only: false,
+            limit: "100".to_owned(),
+            error: None,
+        }
+    }
+}
+
+impl TraceUi {
+    fn tracer(&self) -> Result<Tracer, String> {
+        let range = match (self.start.trim(), self.end.trim()) {
+            ("", "") => None,
+            (start, end) => {
+                let start = crate::debugger::parse_addr(start)?;
+                let end = match end {
+                    "" => start,
+                    s => crate::debugger::parse_addr(s)?,
+                };
+                Some(start.min(end)..=start.max(end))
+            }
+        };
+        let prg_bank = match self.bank.trim() {
+            "" => None,
+            s => Some(s.parse().map_err(|_| format!("invalid bank `{}`", s))?),
+        };
+        let limit = match self.limit.trim() {
+            "" => None,
+            s => Some(
+                s.parse::<usize>()
+                    .map_err(|_| format!("invalid limit `{}`", s))?
+                    * 1_000_000,
+            ),
+        };
+
+        let file = std::fs::File::create(self.path.trim())
+            .map_err(|e| format!("failed to create {}: {}", self.path, e))?;
+        let filter = TraceFilter {
+            range,
+            nmi_only: self.nmi_only,
+            prg_bank,
+        };
+        Ok(Tracer::new(file, filter, limit))
+    }
+}
+
+/// `trace` is the bytes written and whether the trace is done, if one is running
+pub fn trace_window(
+    ctx: &egui::Context,
+    state: &mut TraceUi,
+    trace: Option<(usize, bool)>,
+    sender: &ControlSender,
+) {
+    egui::Window::new("Trace").resizable(false).show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("file");
+            ui.text_edit_singleline(&mut state.path);
+        });
+        ui.horizontal(|ui| {
+            ui.label("pc");
+            ui.add(egui::TextEdit::singleline(&mut state.start).desired_width(48.0));
+            ui.add(
+                egui::TextEdit::singleline(&mut state.end)
+                    .hint_text("end")
+                    .desired_width(48.0),
+            );
+            ui.label("8KB bank");
+            ui.add(egui::TextEdit::singleline(&mut state.bank).desired_width(32.0));
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut state.nmi_only, "only in NMI");
+            ui.label("limit (MB)");
+            ui.add(egui::TextEdit::singleline(&mut state.limit).desired_width(48.0));
+        });
+
+        ui.horizontal(|ui| match trace {
+            Some((written, done)) => {
+                if ui.button("STOP").clicked() {
+                    let _ = sender.send(ControlEvent::StopTrace);
+                }
+                let status = if done { "done" } else { "tracing" };
+                ui.label(format!("{}, {} KB", status, written / 1000));
+            }
+            None => {
+                if ui.button("START").clicked() {
+                    match state.tracer() {
+                        Ok(tracer) => {
+                            let _ = sender.send(ControlEvent::StartTrace(tracer));
+                            state.error = None;
+                        }
+                        Err(e) => state.error = Some(e),
+                    }
+                }
+            }
+        });
+
+        if let Some(e) = &state.error {
+            ui.colored_label(egui::Color32::RED, e.as_str());
+        }
+    });
+}
@@ -1,4 +1,10 @@
-use super::{ControlEvent, ControlSender, EmuContext, SharedEmuContext};
+#[cfg(not(target_arch = "wasm32"))]
+use super::trace::{trace_window, TraceUi};
+use super::{
+    debugger::{debugger_window, disassembly_window, DebuggerUi},
+    emu::FrameOutputRes,
+    ControlEvent, ControlSender, EmuContext, SharedEmuContext,
+};
 use bevy::{
     diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
     image::ImageSampler,
@@ -9,7 +15,12 @@ use bevy_egui::{
     EguiContexts, EguiPrimaryContextPass,
 };
 use leafwing_input_manager::prelude::*;
-use les_nes::{cpu::CpuStatus, InputStates};
+use les_nes::{
+    cart::CartInfo,
+    cpu::{disassemble_around, CpuStatus, Instruction},
+    debug::BreakReason,
+    InputStates,
+};
 
 pub struct UiPlugin {
     pub(crate) emu: SharedEmuContext,
@@ -55,6 +66,15 @@ struct NesStatus {
     ppu_timing: (usize, usize),
     ppu_frames: usize,
     cycles: usize,
+    halted: Option<BreakReason>,
+    listing: Vec<Instruction>,
+    /// bytes written and whether it is done
+    trace: Option<(usize, bool)>,
+    expansion_audio: &'static [&'static str],
+    /// header infos, `None` for the empty cartridge
+    cart_info: Option<CartInfo>,
+    /// prg and chr ram of the cartridge
+    ram_sizes: (usize, usize),
 }
 
 #[derive(Default, Resource)]
@@ -62,10 +82,16 @@ struct UiData {
     debug: bool,
     scale: f32,
     apu_ctrl: [bool; 5],
+    /// one per `NesStatus::expansion_audio`
+    expansion_ctrl: Vec<bool>,
     pat_index: usize,
     nm_index: usize,
     nes_status: NesStatus,
     swap_input: bool,
+    message: Option<String>,
+    debugger: DebuggerUi,
+    #[cfg(not(target_arch = "wasm32"))]
+    trace: TraceUi,
 }
 
 #[derive(Resource)]
@@ -118,6 +144,14 @@ fn ui(
                     pick_rom.write(PickRom);
                 }
             });
+            ui.menu_button("State", |ui| {
+                if ui.button("save (F5)").clicked() {
+                    let _ = control_sender.send(ControlEvent::SaveState);
+                }
+                if ui.button("load (F9)").clicked() {
+                    let _ = control_sender.send(ControlEvent::LoadState);
+                }
+            });
             ui.menu_button("Debug", |ui| {
                 ui.checkbox(&mut ui_data.debug, "debug panels");
                 ui.checkbox(&mut ui_data.swap_input, "swap player");
@@ -131,11 +165,27 @@ fn ui(
     });
 
     egui::CentralPanel::default().show(ctx, |_ui| {
+        if let Some(message) = &ui_data.message {
+            let mut close = false;
+            egui::Window::new("Error")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label(message.as_str());
+                    close = ui.button("OK").clicked();
+                });
+            if close {
+                ui_data.message = None;
+            }
+        }
+
         if ui_data.debug {
             let UiData {
                 pat_index,
                 nm_index,
                 apu_ctrl,
+                expansion_ctrl,
+                nes_status,
                 ..
             } = &mut *ui_data;
 
@@ -165,33 +215,46 @@ fn ui(
                     if changed {
                         let _ = control_sender.send(ControlEvent::AudioCtrl(*apu_ctrl));
                     }
-                });
-            });
 
-            egui::Window::new("CPU").resizable(false).show(ctx, |ui| {
-                ui.vertical(|ui| {
-                    ui.vertical(|ui| {
-                        if let Some(s) = &ui_data.nes_status.cpu_status {
-                            ui.label(format!("A: {:02X} X: {:02X} Y: {:02X}", s.a, s.x, s.y));
-                            ui.label(format!("PC: {:04X} SP: {:04X}", s.pc, s.sp));
-                            ui.label(format!("P: {:?}  {:02X}", s.p, s.p.to_u8()));
-                            ui.label(format!("CYCLES: {}", ui_data.nes_status.cycles));
-                        }
-                    });
-                    ui.horizontal(|ui| {
-                        if ui.button("RESET").clicked() {
-                            let _ = control_sender.send(ControlEvent::Reset);
-                        }
-                        if ui.button("STEP").clicked() {
-                            let _ = control_sender.send(ControlEvent::Step);
-                        }
-                        if ui.button("CONTINUE").clicked() {
-                            let _ = control_sender.send(ControlEvent::Pause);
-                        }
-                    });
+                    let mut changed = false;
+                    for (value, name) in expansion_ctrl.iter_mut().zip(nes_status.expansion_audio) {
+                        changed |= ui.checkbox(value, *name).changed();
+                    }
+                    if changed {
+                        let _ = control_sender
+                            .send(ControlEvent::ExpansionAudioCtrl(expansion_ctrl.clone()));
+                    }
                 });
             });
 
+            let UiData {
+                nes_status,
+                debugger,
+                ..
+            } = &mut *ui_data;
+            debugger_window(
+                ctx,
+                debugger,
+                nes_status.cpu_status.as_ref(),
+                nes_status.cycles,
+                nes_status.halted.as_ref(),
+                control_sender,
+            );
+            disassembly_window(
+                ctx,
+                debugger,
+                &nes_status.listing,
+                nes_status.cpu_status.map(|s| s.pc),
+                control_sender,
+            );
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                let UiData {
+                    nes_status, trace, ..
+                } = &mut *ui_data;
+                trace_window(ctx, trace, nes_status.trace, control_sender);
+            }
+
             egui::Window::new("PPU").resizable(false).show(ctx, |ui| {
                 let s = &ui_data.nes_status;
                 ui.label(format!(
@@ -200,6 +263,20 @@ fn ui(
                 ));
                 ui.label(format!("FRAMES: {:15}", s.ppu_frames));
             });
+
+            egui::Window::new("Cartridge")
+                .resizable(false)
+                .show(ctx, |ui| {
+                    if let Some(info) = &ui_data.nes_status.cart_info {
+                        ui.label(format!("MAPPER: {:03}.{}", info.mapper, info.submapper));
+                        ui.label(format!("PRG ROM: {}KB", info.prg_rom_size / 0x400));
+                        ui.label(format!("CHR ROM: {}KB", info.chr_rom_size / 0x400));
+                        ui.label(format!("MIRRORING: {:?}", info.mirroring));
+                    }
+                    let (prg_ram, chr_ram) = ui_data.nes_status.ram_sizes;
+                    ui.label(format!("PRG RAM: {}KB", prg_ram / 0x400));
+                    ui.label(format!("CHR RAM: {}KB", chr_ram / 0x400));
+                });
         }
 
         egui::Window::new(format!(
@@ -273,11 +350,9 @@ fn sync_emu_status(
     mut textures: ResMut<Assets<Image>>,
     infos: Res<PpuTextures>,
     emu: Res<SharedEmuContextRes>,
+    mut frame: ResMut<FrameOutputRes>,
     mut ui_data: ResMut<UiData>,
 ) {
-    let mut emu = emu.0.lock().unwrap();
-    let EmuContext { cpu, bus, .. } = &mut *emu;
-
     fn image_as_mut(image: Option<&mut Image>) -> &mut [[u8; 4]] {
         image
             .unwrap()
@@ -289,10 +364,34 @@ fn sync_emu_status(
             .0
     }
 
-    let ppu = bus.ppu();
     let infos = &infos.0;
 
-    ppu.render_display(image_as_mut(textures.get_mut(&infos[0].handle)));
+    // the display comes from the frame buffer, it never waits for the emulation
+    if frame.0.updated() {
+        let pixels = frame.0.read();
+        image_as_mut(textures.get_mut(&infos[0].handle))
+            .iter_mut()
+            .zip(pixels.chunks_exact(3))
+            .for_each(|(p, c)| p[..3].copy_from_slice(c));
+    }
+
+    // the rest is only a peek, skip it while a frame is running
+    let Ok(mut emu) = emu.0.try_lock() else {
+        return;
+    };
+    let EmuContext {
+        cpu,
+        bus,
+        message,
+        cart_loaded,
+        ..
+    } = &mut *emu;
+
+    if let Some(message) = message.take() {
+        ui_data.message = Some(message);
+    }
+
+    let ppu = bus.ppu();
     if ui_data.debug {
         let cart = bus.cart();
 
@@ -310,11 +409,30 @@ fn sync_emu_status(
         ppu.render_sprites(cart, image_as_mut(textures.get_mut(&infos[4].handle)));
     }
 
+    // only decode while the debug panels are open
+    let listing = if ui_data.debug {
+        disassemble_around(bus, &cpu.status(), 16, 32)
+    } else {
+        vec![]
+    };
+
+    // a new cartridge starts with all of its channels on
+    let expansion_audio = bus.cart().audio_channels();
+    if std::mem::take(cart_loaded) || expansion_audio != ui_data.nes_status.expansion_audio {
+        ui_data.expansion_ctrl = vec![true; expansion_audio.len()];
+    }
+
     ui_data.nes_status = NesStatus {
         cpu_status: Some(cpu.status()),
         ppu_timing: bus.ppu().timing(),
         ppu_frames: bus.ppu().frame_count(),
         cycles: bus.cycles(),
+        halted: bus.debugger().halted().cloned(),
+        listing,
+        trace: bus.tracer().map(|t| (t.written(), t.done())),
+        expansion_audio,
+        cart_info: bus.cart().info().cloned(),
+        ram_sizes: (bus.cart().prg_ram_size(), bus.cart().chr_ram_size()),
     };
 }
 
@@ -360,6 +478,10 @@ fn handle_inputs(
         let _ = control_sender.send(ControlEvent::Step);
     } else if input.just_pressed(KeyCode::ShiftLeft) {
         let _ = control_sender.send(ControlEvent::Pause);
+    } else if input.just_pressed(KeyCode::F5) {
+        let _ = control_sender.send(ControlEvent::SaveState);
+    } else if input.just_pressed(KeyCode::F9) {
+        let _ = control_sender.send(ControlEvent::LoadState);
     }
 
     let states_p1 = action_to_states(query_p1.single()?);
@@ -393,7 +515,10 @@ fn pick_rom(sender: Res<ControlSenderRes>, mut messages: MessageReader<PickRom>)
         bevy::tasks::IoTaskPool::get()
             .spawn(async move {
                 if let Some(handle) = rfd::AsyncFileDialog::new().pick_file().await {
-                    let _ = sender.send(ControlEvent::LoadCart(handle.read().await));
+                    let _ = sender.send(ControlEvent::LoadCart {
+                        save_key: crate::sram::save_key(&handle),
+                        data: handle.read().await,
+                    });
                 }
             })
             .detach();
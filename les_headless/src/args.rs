@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: les_headless <rom> [options]
+
+options:
+    --frames <n>          number of frames to run (default 600)
+    --until <addr>=<val>  stop early once the byte at addr equals val
+    --input <file>        input script, see below
+    --png <file>          write the last frame as png
+    --wav <file>          write the audio as wav
+    --ram <file>          write the internal ram ($0000-$07ff)
+    --sample-rate <hz>    audio sample rate (default 44100)
+    --trace <file>        log every instruction, in a nestest like format
+    --trace-limit <bytes> stop logging after this many bytes
+
+input script lines are `<frame> <player 1> [player 2]`, buttons are a comma
+separated list of a, b, select, start, up, down, left, right, or `-` for none.
+buttons are held until the next line, `#` starts a comment.
+numbers may be written as decimal or hex with a `$` or `0x` prefix.";
+
+#[derive(Debug)]
+pub struct Args {
+    pub rom: PathBuf,
+    pub frames: usize,
+    pub until: Option<(u16, u8)>,
+    pub input: Option<PathBuf>,
+    pub png: Option<PathBuf>,
+    pub wav: Option<PathBuf>,
+    pub ram: Option<PathBuf>,
+    pub sample_rate: u32,
+    pub trace: Option<PathBuf>,
+    pub trace_limit: Option<usize>,
+}
+
+impl Args {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut rom = None;
+        let mut parsed = Args {
+            rom: PathBuf::new(),
+            frames: 600,
+            until: None,
+            input: None,
+            png: None,
+            wav: None,
+            ram: None,
+            sample_rate: 44_100,
+            trace: None,
+            trace_limit: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--frames" => parsed.frames = parse_num(&value()?)?,
+                "--until" => {
+                    let v = value()?;
+                    let (addr, val) = v
+                        .split_once('=')
+                        .ok_or(format!("expected <addr>=<val>, got {}", v))?;
+                    parsed.until = Some((parse_num(addr)?, parse_num(val)?));
+                }
+                "--input" => parsed.input = Some(value()?.into()),
+                "--png" => parsed.png = Some(value()?.into()),
+                "--wav" => parsed.wav = Some(value()?.into()),
+                "--ram" => parsed.ram = Some(value()?.into()),
+                "--sample-rate" => parsed.sample_rate = parse_num(&value()?)?,
+                "--trace" => parsed.trace = Some(value()?.into()),
+                "--trace-limit" => parsed.trace_limit = Some(parse_num(&value()?)?),
+                _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+                _ if rom.is_none() => rom = Some(arg.into()),
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+
+        parsed.rom = rom.ok_or("missing rom")?;
+        Ok(parsed)
+    }
+}
+
+/// decimal, or hex with a `$` or `0x` prefix
+pub fn parse_num<T: TryFrom<u64>>(s: &str) -> Result<T, String> {
+    let n = if let Some(hex) = s.strip_prefix('$').or_else(|| s.strip_prefix("0x")) {
+        u64::from_str_radix(hex, 16)
+    } else {
+        s.parse()
+    };
+
+    n.ok()
+        .and_then(|n| T::try_from(n).ok())
+        .ok_or(format!("invalid number {}", s))
+}
@@ -0,0 +1,105 @@
+use std::{error::Error, fs, io::BufWriter, process::ExitCode};
+
+use les_nes::{Bus, Cartridge, Cpu, TraceFilter, Tracer};
+
+mod args;
+mod script;
+
+use args::{Args, USAGE};
+use script::InputScript;
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 240;
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => {
+            eprintln!("condition not met after {} frames", args.frames);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// returns false if `--until` was given but never met
+fn run(args: &Args) -> Result<bool, Box<dyn Error>> {
+    let cart = Cartridge::load(&fs::read(&args.rom)?)?;
+    let mut script = match &args.input {
+        Some(path) => InputScript::parse(&fs::read_to_string(path)?)?,
+        None => InputScript::default(),
+    };
+
+    let mut bus = Bus::new(cart);
+    let mut cpu = Cpu::default();
+    bus.reset(&mut cpu);
+    bus.resampler().set_rates(args.sample_rate as _);
+    if let Some(path) = &args.trace {
+        let file = fs::File::create(path)?;
+        bus.start_trace(Tracer::new(file, TraceFilter::default(), args.trace_limit));
+    }
+
+    let mut audio = Vec::new();
+    let mut met = args.until.is_none();
+
+    for frame in 0..args.frames {
+        if let Some((p0, p1)) = script.poll(frame) {
+            bus.set_input0(p0);
+            bus.set_input1(p1);
+        }
+
+        audio.extend_from_slice(bus.run_frame(&mut cpu).samples);
+
+        if let Some((addr, val)) = args.until {
+            if bus.peek(addr) == val {
+                met = true;
+                break;
+            }
+        }
+    }
+
+    if let Some(res) = bus.stop_trace() {
+        res?;
+    }
+
+    if let Some(path) = &args.png {
+        let mut encoder = png::Encoder::new(BufWriter::new(fs::File::create(path)?), WIDTH, HEIGHT);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()?
+            .write_image_data(bus.ppu().display_buf())?;
+    }
+
+    if let Some(path) = &args.wav {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: args.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec)?;
+        for s in audio {
+            writer.write_sample(s)?;
+        }
+        writer.finalize()?;
+    }
+
+    if let Some(path) = &args.ram {
+        let ram: Vec<u8> = (0..0x0800).map(|addr| bus.peek(addr)).collect();
+        fs::write(path, ram)?;
+    }
+
+    Ok(met)
+}
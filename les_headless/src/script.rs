@@ -0,0 +1,72 @@
+use les_nes::InputStates;
+
+use crate::args::parse_num;
+
+/// controller states keyed by the frame they start at
+#[derive(Debug, Default)]
+pub struct InputScript {
+    entries: Vec<(usize, InputStates, InputStates)>,
+    next: usize,
+}
+
+impl InputScript {
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+
+        for (n, line) in src.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let err = |e: String| format!("line {}: {}", n + 1, e);
+            let mut fields = line.split_whitespace();
+            let frame = parse_num(fields.next().unwrap()).map_err(err)?;
+            let p0 = parse_buttons(fields.next().unwrap_or("-")).map_err(err)?;
+            let p1 = parse_buttons(fields.next().unwrap_or("-")).map_err(err)?;
+            if fields.next().is_some() {
+                return Err(err("too many fields".to_owned()));
+            }
+
+            entries.push((frame, p0, p1));
+        }
+
+        entries.sort_by_key(|e| e.0);
+        Ok(Self { entries, next: 0 })
+    }
+
+    /// the states that change at `frame`
+    pub fn poll(&mut self, frame: usize) -> Option<(InputStates, InputStates)> {
+        let mut states = None;
+        while let Some(&(start, p0, p1)) = self.entries.get(self.next) {
+            if start > frame {
+                break;
+            }
+            states = Some((p0, p1));
+            self.next += 1;
+        }
+        states
+    }
+}
+
+fn parse_buttons(s: &str) -> Result<InputStates, String> {
+    let mut states = InputStates::default();
+    if s == "-" {
+        return Ok(states);
+    }
+
+    for button in s.split(',') {
+        match button.to_ascii_lowercase().as_str() {
+            "a" => states.a = true,
+            "b" => states.b = true,
+            "select" => states.select = true,
+            "start" => states.start = true,
+            "up" => states.up = true,
+            "down" => states.down = true,
+            "left" => states.left = true,
+            "right" => states.right = true,
+            _ => return Err(format!("unknown button {}", button)),
+        }
+    }
+    Ok(states)
+}
@@ -1,3 +1,4 @@
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
 use bit_field::BitField;
 
 mod dmc;
@@ -9,12 +10,12 @@ mod units;
 
 use dmc::Dmc;
 use noise::Noise;
-use pulse::Pulse;
+pub(crate) use pulse::Pulse;
 pub use resampler::Resampler;
 use triangle::Triangle;
 use units::*;
 
-trait Channel {
+pub(crate) trait Channel {
     fn sample(&mut self) -> u8;
 
     fn tick(&mut self);
@@ -61,7 +62,8 @@ impl Default for Apu {
 }
 
 impl Apu {
-    pub fn tick(&mut self) {
+    /// `expansion` is the cartridge audio, mixed in as is
+    pub fn tick(&mut self, expansion: f32) {
         let step = self.frame.tick();
         self.frame_tick(step);
 
@@ -80,7 +82,7 @@ impl Apu {
             + self.noise.sample() * 2 * self.channel_ctrl[3]
             + self.dmc.sample() * self.channel_ctrl[4];
         self.resampler
-            .add_sample(PULSE_TABLE[pulse_index as usize] + TND_TABLE[tnd_index as usize]);
+            .add_sample(mix(pulse_index, tnd_index) + expansion);
     }
 
     fn frame_tick(&mut self, step: Step) {
@@ -189,6 +191,37 @@ impl Apu {
     }
 }
 
+impl Snapshot for Apu {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.frame.save_state(w);
+        self.pulse1.save_state(w);
+        self.pulse2.save_state(w);
+        self.triangle.save_state(w);
+        self.noise.save_state(w);
+        self.dmc.save_state(w);
+        w.usize(self.cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.frame.load_state(r)?;
+        self.pulse1.load_state(r)?;
+        self.pulse2.load_state(r)?;
+        self.triangle.load_state(r)?;
+        self.noise.load_state(r)?;
+        self.dmc.load_state(r)?;
+        self.cycles = r.usize()?;
+
+        self.resampler.clear();
+        Ok(())
+    }
+}
+
+/// the non linear mixer, `pulse` is the sum of the pulses and
+/// `tnd` is `3 * triangle + 2 * noise + dmc`
+pub(crate) fn mix(pulse: u8, tnd: u8) -> f32 {
+    PULSE_TABLE[pulse as usize] + TND_TABLE[tnd as usize]
+}
+
 lazy_static::lazy_static! {
     static ref PULSE_TABLE: [f32; 31] = {
         let mut table = [0.0f32; 31];
@@ -1,4 +1,5 @@
 use super::Divider;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
 use bit_field::BitField;
 
 const RATE: [usize; 16] = [
@@ -144,3 +145,45 @@ impl super::Channel for Dmc {
         self.sample_remain > 0
     }
 }
+
+impl Snapshot for Dmc {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.irq_on);
+        w.bool(self.irq_level.is_some());
+        w.bool(self.looping);
+
+        w.u16(self.sample_start_address);
+        w.u16(self.sample_address);
+        w.u16(self.sample_len);
+        w.u16(self.sample_remain);
+        w.bool(self.sample_request.is_some());
+        w.u16(self.sample_request.unwrap_or_default());
+
+        self.timer.save_state(w);
+        w.u8(self.bits_shifter);
+        w.u8(self.bits_remain);
+
+        w.u8(self.output);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.irq_on = r.bool()?;
+        self.irq_level = r.bool()?.then_some(());
+        self.looping = r.bool()?;
+
+        self.sample_start_address = r.u16()?;
+        self.sample_address = r.u16()?;
+        self.sample_len = r.u16()?;
+        self.sample_remain = r.u16()?;
+        let request = r.bool()?;
+        let addr = r.u16()?;
+        self.sample_request = request.then_some(addr);
+
+        self.timer.load_state(r)?;
+        self.bits_shifter = r.u8()?;
+        self.bits_remain = r.u8()?;
+
+        self.output = r.u8()? & 0x7f;
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use super::{Divider, Envelope, LengthCounter};
+use crate::state::{Snapshot, StateError, StateReader, StateResult, StateWriter};
 use bit_field::BitField;
 
 const PERIOD: [usize; 16] = [
@@ -74,3 +75,25 @@ impl super::Channel for Noise {
         self.len_counter.count() > 0
     }
 }
+
+impl Snapshot for Noise {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.envelope.save_state(w);
+        self.len_counter.save_state(w);
+        self.timer.save_state(w);
+        w.u16(self.lfsr);
+        w.u8(self.bit_index as u8);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.envelope.load_state(r)?;
+        self.len_counter.load_state(r)?;
+        self.timer.load_state(r)?;
+        self.lfsr = r.u16()?;
+        self.bit_index = match r.u8()? {
+            b @ (1 | 6) => b as usize,
+            _ => return Err(StateError::Invalid("noise mode")),
+        };
+        Ok(())
+    }
+}
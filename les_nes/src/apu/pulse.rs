@@ -1,4 +1,5 @@
 use super::{Divider, Envelope, LengthCounter, Sweep};
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
 use bit_field::BitField;
 
 const DUTY: [[u8; 8]; 4] = [
@@ -13,6 +14,8 @@ pub struct Pulse {
     len_counter: LengthCounter,
     envelope: Envelope,
     sweep: Sweep,
+    /// the mmc5 pulses have none, so they aren't muted by it either
+    has_sweep: bool,
 
     timer: Divider,
     duty: usize,
@@ -25,18 +28,27 @@ impl Pulse {
             len_counter: LengthCounter::new(),
             envelope: Envelope::new(),
             sweep: Sweep::new(channel2 as usize),
+            has_sweep: true,
 
             timer: Divider::new(),
             duty: 0,
             step: 0,
         }
     }
+
+    pub fn without_sweep() -> Self {
+        Self {
+            has_sweep: false,
+            ..Self::new(false)
+        }
+    }
 }
 
 impl super::Channel for Pulse {
     fn sample(&mut self) -> u8 {
         let duty = DUTY[self.duty][self.step];
-        self.envelope.volume() * duty * self.len_counter.count() * (!self.sweep.muting() as u8)
+        let muted = self.has_sweep && self.sweep.muting();
+        self.envelope.volume() * duty * self.len_counter.count() * (!muted as u8)
     }
 
     fn tick(&mut self) {
@@ -47,7 +59,9 @@ impl super::Channel for Pulse {
 
     fn tick_len(&mut self) {
         self.len_counter.tick();
-        self.sweep.tick(&mut self.timer);
+        if self.has_sweep {
+            self.sweep.tick(&mut self.timer);
+        }
     }
 
     fn tick_eve(&mut self) {
@@ -85,3 +99,24 @@ impl super::Channel for Pulse {
         self.len_counter.count() > 0
     }
 }
+
+impl Snapshot for Pulse {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.len_counter.save_state(w);
+        self.envelope.save_state(w);
+        self.sweep.save_state(w);
+        self.timer.save_state(w);
+        w.u8(self.duty as u8);
+        w.u8(self.step as u8);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.len_counter.load_state(r)?;
+        self.envelope.load_state(r)?;
+        self.sweep.load_state(r)?;
+        self.timer.load_state(r)?;
+        self.duty = r.u8()? as usize & 0b11;
+        self.step = r.u8()? as usize & 0b0111;
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use super::{Divider, LengthCounter};
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
 use bit_field::BitField;
 
 const SEQ: [u8; 32] = [
@@ -91,3 +92,26 @@ impl super::Channel for Triangle {
         self.len_counter.count() > 0
     }
 }
+
+impl Snapshot for Triangle {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.step as u8);
+        self.timer.save_state(w);
+        self.len_counter.save_state(w);
+        w.usize(self.linear_len);
+        w.usize(self.linear_counter);
+        w.bool(self.linear_reload);
+        w.bool(self.linear_control);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.step = r.u8()? as usize % 32;
+        self.timer.load_state(r)?;
+        self.len_counter.load_state(r)?;
+        self.linear_len = r.usize()?;
+        self.linear_counter = r.usize()?;
+        self.linear_reload = r.bool()?;
+        self.linear_control = r.bool()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+
 #[derive(Debug)]
 pub struct Divider {
     period: usize,
@@ -54,3 +56,18 @@ impl Divider {
         self.raw_period
     }
 }
+
+impl Snapshot for Divider {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.period);
+        w.usize(self.raw_period);
+        w.usize(self.counter);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.period = r.usize()?;
+        self.raw_period = r.usize()?;
+        self.counter = r.usize()?;
+        Ok(())
+    }
+}
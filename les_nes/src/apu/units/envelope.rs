@@ -1,4 +1,5 @@
 use super::Divider;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
 use bit_field::BitField;
 
 #[derive(Debug)]
@@ -56,3 +57,24 @@ impl Envelope {
         self.restart = true;
     }
 }
+
+impl Snapshot for Envelope {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.divider.save_state(w);
+        w.u8(self.volume);
+        w.u8(self.counter);
+        w.bool(self.looping);
+        w.bool(self.constant);
+        w.bool(self.restart);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.divider.load_state(r)?;
+        self.volume = r.u8()?;
+        self.counter = r.u8()?;
+        self.looping = r.bool()?;
+        self.constant = r.bool()?;
+        self.restart = r.bool()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
 use bit_field::BitField;
 
 const FRAME_FREQUENCY: f32 = 240.0;
@@ -89,3 +90,22 @@ impl FrameCounter {
         std::mem::take(&mut self.irq_level)
     }
 }
+
+impl Snapshot for FrameCounter {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.f32(self.counter);
+        w.usize(self.step);
+        w.bool(self.mode == Mode::Step5);
+        w.bool(self.irq_on);
+        w.bool(self.irq_level);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.counter = r.f32()?;
+        self.step = r.usize()? % 5;
+        self.mode = if r.bool()? { Mode::Step5 } else { Mode::Step4 };
+        self.irq_on = r.bool()?;
+        self.irq_level = r.bool()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+
 const LEN_TABLE: [u8; 32] = [
     0x0a, 0xfe, 0x14, 0x02, 0x28, 0x04, 0x50, 0x06, 0xa0, 0x08, 0x3c, 0x0a, 0x0e, 0x0c, 0x1a, 0x0e,
     0x0c, 0x10, 0x18, 0x12, 0x30, 0x14, 0x60, 0x16, 0xc0, 0x18, 0x48, 0x1a, 0x10, 0x1c, 0x20, 0x1e,
@@ -46,3 +48,18 @@ impl LengthCounter {
         }
     }
 }
+
+impl Snapshot for LengthCounter {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.counter);
+        w.bool(self.enabled);
+        w.bool(self.halt);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.counter = r.u8()?;
+        self.enabled = r.bool()?;
+        self.halt = r.bool()?;
+        Ok(())
+    }
+}
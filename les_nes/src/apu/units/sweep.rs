@@ -1,4 +1,5 @@
 use super::Divider;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
 use bit_field::BitField;
 
 #[derive(Debug)]
@@ -69,3 +70,26 @@ impl Sweep {
         self.muting
     }
 }
+
+impl Snapshot for Sweep {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.divider.save_state(w);
+        w.bool(self.enable);
+        w.bool(self.neg);
+        w.u8(self.shift);
+        w.usize(self.shifter);
+        w.bool(self.reload);
+        w.bool(self.muting);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.divider.load_state(r)?;
+        self.enable = r.bool()?;
+        self.neg = r.bool()?;
+        self.shift = r.u8()? & 0b0111;
+        self.shifter = r.usize()?;
+        self.reload = r.bool()?;
+        self.muting = r.bool()?;
+        Ok(())
+    }
+}
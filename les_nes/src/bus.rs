@@ -1,5 +1,10 @@
+use std::io;
+
 use self::dma::Dma;
 use self::joystick::Joystick;
+use crate::debug::{Access, AddrSpace, BreakReason, Debugger};
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use crate::trace::Tracer;
 use crate::{cpu::Interrupt, Apu, Cartridge, Cpu, Ppu, Resampler};
 
 pub use joystick::InputStates;
@@ -19,8 +24,19 @@ pub struct Bus {
     cart: Cartridge,
     joystick: Joystick,
     dma: Dma,
+    debugger: Debugger,
+    tracer: Option<Tracer>,
 
     cycles: usize,
+    samples: Vec<i16>,
+}
+
+/// output of a completed frame
+pub struct Frame<'a> {
+    /// 256x240 rgb pixels
+    pub pixels: &'a [u8],
+    /// audio samples generated during the frame, at the resampler rate
+    pub samples: &'a [i16],
 }
 
 impl Bus {
@@ -34,8 +50,11 @@ impl Bus {
             cart,
             joystick: Default::default(),
             dma: Default::default(),
+            debugger: Default::default(),
+            tracer: None,
 
             cycles: 0,
+            samples: Vec::new(),
         }
     }
 
@@ -48,20 +67,70 @@ impl Bus {
                 self.tick();
             }
         } else {
+            if self.debugger.on_exec(&cpu.status()) {
+                return;
+            }
+
+            if let Some(mut tracer) = self.tracer.take() {
+                tracer.trace(self, cpu);
+                self.tracer = Some(tracer);
+            }
+
             cpu.exec(self);
 
             if self.ppu.poll_nmi() {
                 cpu.serve_interrupt(Interrupt::NMI, self);
+                self.debugger.on_interrupt(Interrupt::NMI);
+                if let Some(tracer) = &mut self.tracer {
+                    tracer.on_nmi(cpu.status().sp);
+                }
             } else if !cpu.interrupt_disabled() && (self.apu.poll_irq() || self.cart.poll_irq()) {
                 cpu.serve_interrupt(Interrupt::IRQ, self);
+                self.debugger.on_interrupt(Interrupt::IRQ);
             }
         }
     }
 
+    /// run until the ppu finishes the current frame, or the debugger halts
+    ///
+    /// drains the resampler, so it shouldn't be mixed with reading samples directly.
+    pub fn run_frame(&mut self, cpu: &mut Cpu) -> Frame<'_> {
+        let frame = self.ppu.frame_count();
+        self.run_until(cpu, |bus, _| bus.ppu.frame_count() != frame);
+
+        let resampler = self.apu.resampler();
+        resampler.end_frame();
+        self.samples.resize(resampler.avail(), 0);
+        let n = resampler.read_samples(&mut self.samples);
+        self.samples.truncate(n);
+
+        Frame {
+            pixels: self.ppu.display_buf(),
+            samples: &self.samples,
+        }
+    }
+
+    /// run for at least `cycles` cpu cycles, returns the cycles actually run
+    pub fn run_cycles(&mut self, cpu: &mut Cpu, cycles: usize) -> usize {
+        let start = self.cycles;
+        self.run_until(cpu, |bus, _| bus.cycles - start >= cycles);
+        self.cycles - start
+    }
+
+    /// run whole instructions until `pred` holds, it is checked before each one
+    ///
+    /// also stops when the debugger halts, nothing runs until it is resumed.
+    pub fn run_until<F: FnMut(&Bus, &Cpu) -> bool>(&mut self, cpu: &mut Cpu, mut pred: F) {
+        while self.debugger.halted().is_none() && !pred(self, cpu) {
+            self.exec(cpu);
+        }
+    }
+
     pub(crate) fn tick(&mut self) {
         self.cycles += 1;
 
-        self.apu.tick();
+        self.cart.tick();
+        self.apu.tick(self.cart.audio_output());
         if let Some(addr) = self.apu.dmc_request() {
             let data = self.inspect(addr);
             self.tick_ppu();
@@ -81,14 +150,30 @@ impl Bus {
 
     pub fn read(&mut self, addr: u16) -> u8 {
         self.tick();
-        self.inspect(addr)
+
+        if self.debugger.watching() {
+            let vram_addr = self.ppu.vram_addr();
+            let data = self.inspect(addr);
+            self.watch(addr, vram_addr, Access::Read, data);
+            data
+        } else {
+            self.inspect(addr)
+        }
     }
 
     pub fn write(&mut self, addr: u16, data: u8) {
         self.tick();
+
+        if self.debugger.watching() {
+            self.watch(addr, self.ppu.vram_addr(), Access::Write, data);
+        }
+
         match addr {
             0x0000..=0x1fff => self.ram[addr as usize & 0x07ff] = data,
-            0x2000..=0x3fff => self.ppu.write(&mut self.cart, addr, data),
+            0x2000..=0x3fff => {
+                self.ppu.write(&mut self.cart, addr, data);
+                self.cart.write_ppu_reg(addr, data);
+            }
             0x4000..=0x4013 => self.apu.write(addr, data),
             0x4014 => self.dma.start(self.cycles, data),
             0x4015 | 0x4017 => self.apu.write(addr, data),
@@ -98,10 +183,18 @@ impl Bus {
         }
     }
 
+    fn watch(&mut self, addr: u16, vram_addr: u16, access: Access, data: u8) {
+        self.debugger.on_access(AddrSpace::Cpu, addr, access, data);
+        if (0x2000..=0x3fff).contains(&addr) && addr & 0x07 == 0x07 {
+            self.debugger
+                .on_access(AddrSpace::Ppu, vram_addr, access, data);
+        }
+    }
+
     pub fn inspect(&mut self, addr: u16) -> u8 {
         match addr {
             0x0000..=0x1fff => self.ram[addr as usize & 0x07ff],
-            0x2000..=0x3fff => self.ppu.read(&self.cart, addr),
+            0x2000..=0x3fff => self.ppu.read(&mut self.cart, addr),
             0x4000..=0x4015 => self.apu.read(addr), // except 0x4014
             0x4016..=0x4017 => self.joystick.read(addr),
             0x4018..=0x401f => self.io_regs[addr as usize - 0x4000],
@@ -109,6 +202,16 @@ impl Bus {
         }
     }
 
+    /// read without side effects, i/o registers read as 0
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1fff => self.ram[addr as usize & 0x07ff],
+            0x2000..=0x4017 => 0,
+            0x4018..=0x401f => self.io_regs[addr as usize - 0x4000],
+            0x4020..=0xffff => self.cart.peek(addr),
+        }
+    }
+
     pub fn set_input0(&mut self, states: InputStates) {
         self.joystick.set_input0(states);
     }
@@ -138,10 +241,60 @@ impl Bus {
         &self.cart
     }
 
+    pub fn cart_mut(&mut self) -> &mut Cartridge {
+        &mut self.cart
+    }
+
     pub fn apu(&self) -> &Apu {
         &self.apu
     }
 
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    /// run over a subroutine call, same as a single step for anything else
+    pub fn step_over(&mut self, cpu: &mut Cpu) {
+        const JSR: u8 = 0x20;
+
+        let s = cpu.status();
+        if self.peek(s.pc) == JSR {
+            self.debugger.run_to(s.pc.wrapping_add(3), Some(s.sp));
+        } else {
+            self.debugger.resume();
+            self.exec(cpu);
+            self.debugger.halt(BreakReason::Target(cpu.status().pc));
+        }
+    }
+
+    /// run until the current subroutine returns
+    pub fn step_out(&mut self, cpu: &Cpu) {
+        self.debugger.run_to_return(cpu.status().sp);
+    }
+
+    /// run until the pc reaches `addr`
+    pub fn run_to(&mut self, addr: u16) {
+        self.debugger.run_to(addr, None);
+    }
+
+    /// log every instruction from now on, replacing any running trace
+    pub fn start_trace(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// end the trace, see `Tracer::finish`
+    pub fn stop_trace(&mut self) -> Option<io::Result<usize>> {
+        self.tracer.take().map(Tracer::finish)
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
     pub fn resampler(&mut self) -> &mut Resampler {
         self.apu.resampler()
     }
@@ -151,7 +304,68 @@ impl Bus {
         self.apu.set_channels(states);
     }
 
+    /// override the cartridge audio channels, see `Cartridge::audio_channels`
+    pub fn set_expansion_audio_control(&mut self, states: &[bool]) {
+        self.cart.set_audio_channels(states);
+    }
+
     pub fn load_cart(&mut self, cart: Cartridge) {
         self.cart = cart;
     }
+
+    /// snapshot the whole machine
+    pub fn save_state(&self, cpu: &Cpu) -> Vec<u8> {
+        let mut w = StateWriter::new(self.cart.hash());
+        cpu.save_state(&mut w);
+        Snapshot::save_state(self, &mut w);
+        w.finish()
+    }
+
+    /// restore a snapshot made by [`Bus::save_state`] with the same rom,
+    /// the machine is left untouched on error
+    pub fn load_state(&mut self, cpu: &mut Cpu, data: &[u8]) -> StateResult<()> {
+        let mut r = StateReader::new(data, self.cart.hash())?;
+
+        let backup = self.save_state(cpu);
+        let result = cpu
+            .load_state(&mut r)
+            .and_then(|_| Snapshot::load_state(self, &mut r))
+            .and_then(|_| r.finish());
+
+        if result.is_err() {
+            let mut r = StateReader::new(&backup, self.cart.hash()).unwrap();
+            cpu.load_state(&mut r).unwrap();
+            Snapshot::load_state(self, &mut r).unwrap();
+        }
+        result
+    }
+}
+
+impl Snapshot for Bus {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(self.ram.as_ref());
+        w.bytes(self.io_regs.as_ref());
+
+        self.apu.save_state(w);
+        self.ppu.save_state(w);
+        self.cart.save_state(w);
+        self.joystick.save_state(w);
+        self.dma.save_state(w);
+
+        w.usize(self.cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        r.bytes(self.ram.as_mut())?;
+        r.bytes(self.io_regs.as_mut())?;
+
+        self.apu.load_state(r)?;
+        self.ppu.load_state(r)?;
+        self.cart.load_state(r)?;
+        self.joystick.load_state(r)?;
+        self.dma.load_state(r)?;
+
+        self.cycles = r.usize()?;
+        Ok(())
+    }
 }
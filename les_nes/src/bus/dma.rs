@@ -1,3 +1,5 @@
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+
 #[derive(Debug, Default)]
 pub struct Dma {
     dma_active: bool,
@@ -38,3 +40,20 @@ impl Dma {
         }
     }
 }
+
+impl Snapshot for Dma {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.dma_active);
+        w.u16(self.dma_addr);
+        w.u16(self.dma_step);
+        w.u8(self.delay_ticks);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.dma_active = r.bool()?;
+        self.dma_addr = r.u16()?;
+        self.dma_step = r.u16()?;
+        self.delay_ticks = r.u8()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,5 @@
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+
 #[derive(Debug, Default)]
 pub struct Joystick {
     input0: Input,
@@ -39,7 +41,7 @@ impl Joystick {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct InputStates {
     pub a: bool,
     pub b: bool,
@@ -52,7 +54,7 @@ pub struct InputStates {
 }
 
 impl InputStates {
-    fn to_u8(&self) -> u8 {
+    fn to_u8(self) -> u8 {
         ((!self.a as u8) << 0)
             | ((!self.b as u8) << 1)
             | ((!self.select as u8) << 2)
@@ -62,6 +64,19 @@ impl InputStates {
             | ((!self.left as u8) << 6)
             | ((!self.right as u8) << 7)
     }
+
+    fn from_u8(b: u8) -> Self {
+        Self {
+            a: b & (1 << 0) == 0,
+            b: b & (1 << 1) == 0,
+            select: b & (1 << 2) == 0,
+            start: b & (1 << 3) == 0,
+            up: b & (1 << 4) == 0,
+            down: b & (1 << 5) == 0,
+            left: b & (1 << 6) == 0,
+            right: b & (1 << 7) == 0,
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -81,3 +96,22 @@ impl Input {
         !b & 0b01
     }
 }
+
+impl Snapshot for Joystick {
+    fn save_state(&self, w: &mut StateWriter) {
+        for input in [&self.input0, &self.input1] {
+            w.u8(input.states.to_u8());
+            w.u8(input.input);
+        }
+        w.bool(self.reading);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        for input in [&mut self.input0, &mut self.input1] {
+            input.states = InputStates::from_u8(r.u8()?);
+            input.input = r.u8()?;
+        }
+        self.reading = r.bool()?;
+        Ok(())
+    }
+}
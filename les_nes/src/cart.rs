@@ -1,12 +1,40 @@
-use bit_field::BitField;
+use crate::state::{crc32, Snapshot, StateError, StateReader, StateResult, StateWriter};
+use header::{HEADER_SIZE, TRAINER_SIZE};
+use std::fmt;
 
+pub use header::{CartInfo, ConsoleType, HeaderFormat, Timing};
+
+mod header;
 mod mapper000;
 mod mapper001;
 mod mapper002;
 mod mapper003;
 mod mapper004;
+mod mapper005;
+mod mapper007;
+mod mapper009;
+mod mapper010;
+mod mapper011;
+mod mapper013;
+mod mapper019;
+mod mapper021;
+mod mapper024;
+mod mapper030;
+mod mapper032;
+mod mapper034;
+mod mapper066;
+mod mapper069;
+mod mapper071;
+mod mapper085;
+mod mapper206;
+mod mapper225;
+mod mapper226;
+mod mapper227;
+mod mapper228;
+mod mapper231;
 
 const EXPANSION_ROM_SIZE: usize = 0x1fe0;
+/// ram of boards whose header doesn't give a size
 const PRG_RAM_SIZE: usize = 0x2000;
 const CHR_RAM_SIZE: usize = 0x2000;
 
@@ -28,122 +56,398 @@ pub enum Mirroring {
     FourScreen = 4,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    BadMagic,
+    TruncatedHeader,
+    TruncatedTrainer,
+    TruncatedPrg {
+        expected: usize,
+        found: usize,
+    },
+    TruncatedChr {
+        expected: usize,
+        found: usize,
+    },
+    UnsupportedMapper(u16),
+    InvalidBanks {
+        mapper: u16,
+        prg_banks: usize,
+        chr_banks: usize,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::BadMagic => write!(f, "not an iNES or NES 2.0 rom"),
+            LoadError::TruncatedHeader => write!(f, "rom header is truncated"),
+            LoadError::TruncatedTrainer => write!(f, "rom trainer is truncated"),
+            LoadError::TruncatedPrg { expected, found } => write!(
+                f,
+                "PRG ROM is truncated, expected {} bytes, found {}",
+                expected, found
+            ),
+            LoadError::TruncatedChr { expected, found } => write!(
+                f,
+                "CHR ROM is truncated, expected {} bytes, found {}",
+                expected, found
+            ),
+            LoadError::UnsupportedMapper(n) => write!(f, "mapper {:03} is not supported", n),
+            LoadError::InvalidBanks {
+                mapper,
+                prg_banks,
+                chr_banks,
+            } => write!(
+                f,
+                "invalid banks for mapper {:03}, prg: {}, chr: {}",
+                mapper, prg_banks, chr_banks
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
 pub struct Cartridge {
     expansion: Box<[u8; EXPANSION_ROM_SIZE]>,
-    prg_ram: Box<[u8; PRG_RAM_SIZE]>,
+    prg_ram: Vec<u8>,
     prg_rom: Vec<u8>,
-    chr_ram: Box<[u8; CHR_RAM_SIZE]>,
+    chr_ram: Vec<u8>,
     chr_rom: Vec<u8>,
+    hash: u32,
+    info: Option<CartInfo>,
+    battery: bool,
+    sram_dirty: bool,
 
-    mapper: Box<dyn Mapper + Send + Sync>,
+    mapper: BoxedMapper,
 }
 
 impl Cartridge {
     pub fn empty() -> Self {
         Cartridge {
             expansion: Box::new([0u8; EXPANSION_ROM_SIZE]),
-            prg_ram: Box::new([0u8; PRG_RAM_SIZE]),
+            prg_ram: vec![0; PRG_RAM_SIZE],
             prg_rom: Vec::new(),
-            chr_ram: Box::new([0u8; CHR_RAM_SIZE]),
+            chr_ram: vec![0; CHR_RAM_SIZE],
             chr_rom: Vec::new(),
+            hash: 0,
+            info: None,
+            battery: false,
+            sram_dirty: false,
 
             mapper: Box::new(NullMapper),
         }
     }
 
-    pub fn load(data: &[u8]) -> Option<Self> {
-        if data[..4] != [b'N', b'E', b'S', 0x1a] {
-            return None;
-        }
-        if data[7] & 0b1100 == 0b1100 {
-            panic!("iNes 2.0");
-        }
+    pub fn load(data: &[u8]) -> Result<Self, LoadError> {
+        let header = data.get(..HEADER_SIZE).ok_or(LoadError::TruncatedHeader)?;
+        let info = CartInfo::parse(header.try_into().unwrap()).ok_or(LoadError::BadMagic)?;
 
-        let f6 = data[6];
-        let _ram = f6.get_bit(1);
-        let trainer = f6.get_bit(2);
-        let mirroring = if f6.get_bit(3) {
-            Mirroring::FourScreen
-        } else if f6.get_bit(0) {
-            Mirroring::Vertical
-        } else {
-            Mirroring::Horizontal
-        };
+        let offset = HEADER_SIZE + (info.trainer as usize) * TRAINER_SIZE;
+        let data = data.get(offset..).ok_or(LoadError::TruncatedTrainer)?;
+
+        let prg_rom = data
+            .get(..info.prg_rom_size)
+            .ok_or(LoadError::TruncatedPrg {
+                expected: info.prg_rom_size,
+                found: data.len(),
+            })?
+            .to_vec();
 
-        let mapper_type = (data[7] & 0xf0) | (f6 >> 4);
+        let data = &data[prg_rom.len()..];
+        let chr_rom = data
+            .get(..info.chr_rom_size)
+            .ok_or(LoadError::TruncatedChr {
+                expected: info.chr_rom_size,
+                found: data.len(),
+            })?
+            .to_vec();
 
-        let offset = 0x10 + (trainer as usize) * 0x200;
-        let prg_banks = data[4] as usize;
-        let prg_rom = data[offset..][..(prg_banks * 0x4000)].to_vec();
+        let prg_banks = prg_rom.len() / 0x4000;
+        let chr_banks = chr_rom.len() / 0x2000;
+        let mirroring = info.mirroring;
 
-        let offset = offset + prg_rom.len();
-        let chr_banks = data[5] as usize;
-        let chr_len = chr_banks * 0x2000;
+        let hash = {
+            let mut rom = prg_rom.clone();
+            rom.extend_from_slice(&chr_rom);
+            crc32(&rom)
+        };
 
-        let mut chr_rom = vec![0u8; chr_banks * 0x2000];
-        chr_rom[..chr_len].copy_from_slice(&data[offset..][..chr_len]);
+        let invalid_banks = || LoadError::InvalidBanks {
+            mapper: info.mapper,
+            prg_banks,
+            chr_banks,
+        };
+        let mut prg_ram_size = info.prg_ram_size + info.prg_nvram_size;
+        let mut chr_ram_size = info.chr_ram_size + info.chr_nvram_size;
+        let mapper = match info.mapper {
+            0 => mapper000::Mapper000::new(mirroring, prg_banks).map(boxed),
+            1 => mapper001::Mapper001::new(mirroring, prg_banks, prg_ram_size).map(boxed),
+            2 => mapper002::Mapper002::new(mirroring, prg_banks, info.submapper).map(boxed),
+            3 => mapper003::Mapper003::new(mirroring, prg_banks, chr_banks, info.submapper)
+                .map(boxed),
+            4 | 118 | 119 => {
+                mapper004::Mapper004::new(info.mapper, info.submapper, mirroring, prg_banks)
+                    .map(boxed)
+            }
+            5 => mapper005::Mapper005::new(prg_banks).map(boxed),
+            7 => mapper007::Mapper007::new(prg_banks, info.submapper).map(boxed),
+            9 => mapper009::Mapper009::new(mirroring, prg_banks, chr_banks).map(boxed),
+            10 => mapper010::Mapper010::new(mirroring, prg_banks, chr_banks).map(boxed),
+            11 => mapper011::Mapper011::new(mirroring, prg_banks, chr_banks).map(boxed),
+            13 => mapper013::Mapper013::new(mirroring, prg_banks, chr_banks).map(boxed),
+            19 => mapper019::Mapper019::new(prg_banks, chr_banks).map(boxed),
+            21 | 22 | 23 | 25 => {
+                mapper021::Mapper021::new(info.mapper, info.submapper, mirroring, prg_banks)
+                    .map(boxed)
+            }
+            24 | 26 => mapper024::Mapper024::new(prg_banks, info.mapper == 26).map(boxed),
+            30 => mapper030::Mapper030::new(mirroring, prg_banks, info.battery).map(boxed),
+            32 => mapper032::Mapper032::new(mirroring, prg_banks, info.submapper).map(boxed),
+            34 => mapper034::Mapper034::new(mirroring, prg_banks, chr_banks, info.submapper)
+                .map(boxed),
+            66 => mapper066::Mapper066::new(mirroring, prg_banks, chr_banks).map(boxed),
+            69 => mapper069::Mapper069::new(mirroring, prg_banks).map(boxed),
+            71 => mapper071::Mapper071::new(mirroring, prg_banks, info.submapper).map(boxed),
+            85 => mapper085::Mapper085::new(prg_banks, info.submapper).map(boxed),
+            206 => mapper206::Mapper206::new(mirroring, prg_banks).map(boxed),
+            225 => mapper225::Mapper225::new(mirroring, prg_banks, chr_banks).map(boxed),
+            226 => mapper226::Mapper226::new(prg_banks).map(boxed),
+            227 => mapper227::Mapper227::new(prg_banks).map(boxed),
+            228 => mapper228::Mapper228::new(mirroring, prg_banks, chr_banks).map(boxed),
+            231 => mapper231::Mapper231::new(prg_banks).map(boxed),
+            n => return Err(LoadError::UnsupportedMapper(n)),
+        }
+        .ok_or_else(invalid_banks)?;
 
-        println!("MAPPER: {:03}", mapper_type);
-        println!("PRG ROM: {} * 16KB", prg_banks);
-        println!("CHR ROM: {} * 8KB", chr_banks);
-        println!("MIRRORING: {:?}", mirroring);
+        // iNES only knows 8 KB of chr ram, the board may have more
+        if info.format == HeaderFormat::INes {
+            prg_ram_size = prg_ram_size.max(mapper.min_prg_ram_size());
+            chr_ram_size = chr_ram_size.max(mapper.min_chr_ram_size());
+        }
+        if chr_rom.is_empty() && chr_ram_size == 0 {
+            chr_ram_size = CHR_RAM_SIZE;
+        }
 
-        Some(Self {
+        Ok(Self {
             expansion: Box::new([0u8; EXPANSION_ROM_SIZE]),
-            prg_ram: Box::new([0u8; PRG_RAM_SIZE]),
+            prg_ram: vec![0; prg_ram_size],
             prg_rom,
-            chr_ram: Box::new([0u8; CHR_RAM_SIZE]),
+            chr_ram: vec![0; chr_ram_size],
             chr_rom,
+            hash,
+            battery: info.battery,
+            sram_dirty: false,
 
-            mapper: match mapper_type {
-                0 => Box::new(mapper000::Mapper000::new(mirroring, prg_banks)),
-                1 => Box::new(mapper001::Mapper001::new(mirroring, prg_banks)),
-                2 | 66 => Box::new(mapper002::Mapper002::new(mirroring, prg_banks)),
-                3 => Box::new(mapper003::Mapper003::new(mirroring, prg_banks, chr_banks)),
-                4 => Box::new(mapper004::Mapper004::new(mirroring, prg_banks)),
-                _ => unimplemented!("unimplemented mapper type: {}", mapper_type),
-            },
+            mapper,
+            info: Some(info),
         })
     }
 
-    pub fn read(&self, addr: u16) -> u8 {
+    /// a cpu read, mapper registers may have side effects
+    pub fn read(&mut self, addr: u16) -> u8 {
         match addr {
-            0x4020..=0x5fff => self.expansion[addr as usize - 0x4020],
-            0x6000..=0x7fff => self.prg_ram[addr as usize - 0x6000],
-            0x8000..=0xffff => self.mapper.read_prg(self.prg_rom.as_ref(), addr),
-            _ => unreachable!(),
+            0x4020..=0x5fff => self
+                .mapper
+                .read_low(addr)
+                .unwrap_or(self.expansion[addr as usize - 0x4020]),
+            _ => self.read_prg_space(addr),
         }
     }
 
-    pub fn write(&mut self, addr: u16, data: u8) {
+    /// read without side effects
+    pub fn peek(&self, addr: u16) -> u8 {
         match addr {
-            0x4020..=0x5fff => self.expansion[addr as usize - 0x4020] = data,
-            0x6000..=0x7fff => self.prg_ram[addr as usize - 0x6000] = data,
-            0x8000..=0xffff => self.mapper.write_prg(self.prg_rom.as_mut(), addr, data),
-            _ => unreachable!(),
+            0x4020..=0x5fff => self
+                .mapper
+                .peek_low(addr)
+                .unwrap_or(self.expansion[addr as usize - 0x4020]),
+            _ => self.read_prg_space(addr),
+        }
+    }
+
+    /// $6000-$FFFF, prg ram where the mapper put it and rom everywhere else
+    fn read_prg_space(&self, addr: u16) -> u8 {
+        match self.prg_ram_index(addr) {
+            Some(i) if self.mapper.prg_ram_readable(addr) => self.prg_ram[i],
+            Some(_) => 0,
+            None => self.mapper.read_prg(self.prg_rom.as_ref(), addr),
         }
     }
 
+    /// where the mapper put `addr` in prg ram, if the board has any
+    fn prg_ram_index(&self, addr: u16) -> Option<usize> {
+        let len = self.prg_ram.len();
+        (len > 0)
+            .then(|| self.mapper.prg_ram_offset(addr))
+            .flatten()
+            .map(|i| i % len)
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        if (0x4020..=0x5fff).contains(&addr) {
+            self.expansion[addr as usize - 0x4020] = data;
+        } else if let Some(i) = self.prg_ram_index(addr) {
+            if self.mapper.prg_ram_writable(addr) {
+                self.prg_ram[i] = data;
+                self.sram_dirty |= self.battery;
+            }
+        } else if addr >= 0x8000 {
+            self.mapper.write_prg(self.prg_rom.as_mut(), addr, data);
+        }
+
+        if addr < 0x8000 {
+            self.mapper.write_low(addr, data);
+        }
+    }
+
+    /// chr rom, or chr ram on boards without rom, both banked by the mapper
     pub fn read_chr(&self, addr: u16) -> u8 {
-        if !self.chr_rom.is_empty() {
-            self.mapper.read_chr(self.chr_rom.as_ref(), addr)
+        if self.chr_rom.is_empty() {
+            self.mapper.read_chr(self.chr_ram.as_ref(), addr)
+        } else if let Some(i) = self.chr_ram_index(addr) {
+            self.chr_ram[i]
         } else {
-            self.chr_ram[addr as usize]
+            self.mapper.read_chr(self.chr_rom.as_ref(), addr)
         }
     }
 
+    /// writes to chr rom are dropped
     pub fn write_chr(&mut self, addr: u16, data: u8) {
-        self.mapper.write_chr(self.chr_ram.as_mut(), addr, data)
+        if self.chr_rom.is_empty() {
+            self.mapper.write_chr(self.chr_ram.as_mut(), addr, data);
+        } else if let Some(i) = self.chr_ram_index(addr) {
+            self.chr_ram[i] = data;
+        }
+    }
+
+    /// chr ram next to chr rom, where the mapper put `addr` in it
+    fn chr_ram_index(&self, addr: u16) -> Option<usize> {
+        let len = self.chr_ram.len();
+        (len > 0)
+            .then(|| self.mapper.chr_ram_offset(addr))
+            .flatten()
+            .map(|i| i % len)
+    }
+
+    /// where a pattern table address lands in ciram, if the mapper put ciram there
+    pub fn chr_ciram_addr(&self, addr: u16) -> Option<usize> {
+        self.mapper.chr_ciram_offset(addr)
     }
 
     pub fn nm_addr(&self, addr: u16) -> usize {
         let n = (addr as usize & 0xeff) >> 10;
         let addr = addr as usize & 0x3ff;
-        MIRRORING_MAP[self.mapper.mirroring() as usize][n] + addr
+        self.mapper.ciram_offset(n) + addr
+    }
+
+    /// a nametable byte the mapper supplies instead of ciram
+    pub fn read_nametable(&self, addr: u16) -> Option<u8> {
+        self.mapper
+            .read_nametable(self.chr_rom.as_ref(), addr & 0x2fff)
+    }
+
+    /// true if the mapper took the write, otherwise it goes to ciram
+    pub fn write_nametable(&mut self, addr: u16, data: u8) -> bool {
+        self.mapper.write_nametable(addr & 0x2fff, data)
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        self.mapper.mirroring()
     }
 
-    pub(crate) fn update_scanline(&mut self) {
-        self.mapper.update_scanline();
+    /// header infos, `None` for the empty cartridge
+    pub fn info(&self) -> Option<&CartInfo> {
+        self.info.as_ref()
+    }
+
+    /// prg ram as the board got it, the header size or what the mapper needs
+    pub fn prg_ram_size(&self) -> usize {
+        self.prg_ram.len()
+    }
+
+    pub fn chr_ram_size(&self) -> usize {
+        self.chr_ram.len()
+    }
+
+    /// battery backed prg ram, or the whole prg of flash boards,
+    /// `None` if the cartridge has neither
+    pub fn sram(&self) -> Option<&[u8]> {
+        if self.mapper.prg_flash() {
+            Some(self.prg_rom.as_ref())
+        } else {
+            self.battery.then_some(self.prg_ram.as_ref())
+        }
+    }
+
+    /// restore what `sram` returned, e.g. from a .sav file
+    pub fn load_sram(&mut self, data: &[u8]) {
+        if self.mapper.prg_flash() {
+            // a flash image of another rom size is from another game
+            if data.len() == self.prg_rom.len() {
+                self.prg_rom.copy_from_slice(data);
+            }
+        } else if self.battery {
+            let len = data.len().min(self.prg_ram.len());
+            self.prg_ram[..len].copy_from_slice(&data[..len]);
+        }
+        self.sram_dirty = false;
+        self.mapper.poll_flash_dirty();
+    }
+
+    /// whether the battery backed ram or the flash changed since the last poll
+    pub fn poll_sram_dirty(&mut self) -> bool {
+        let flash = self.mapper.poll_flash_dirty();
+        std::mem::take(&mut self.sram_dirty) | flash
+    }
+
+    /// crc32 of prg and chr rom
+    pub fn hash(&self) -> u32 {
+        self.hash
+    }
+
+    /// offset into prg rom of a cpu address, `None` if it isn't rom
+    pub fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xffff => self.mapper.prg_offset(addr),
+            _ => None,
+        }
+    }
+
+    /// every fetch of the ppu, from rendering or $2007
+    pub(crate) fn ppu_read(&mut self, addr: u16) {
+        self.mapper.ppu_read(addr);
+    }
+
+    /// every address the ppu puts on its bus, fetches as well as $2006 and $2007
+    pub(crate) fn ppu_address(&mut self, addr: u16) {
+        self.mapper.ppu_address(addr);
+    }
+
+    /// cpu writes to $2000-$3FFF
+    pub(crate) fn write_ppu_reg(&mut self, addr: u16, data: u8) {
+        self.mapper.write_ppu_reg(0x2000 + (addr & 0x07), data);
+    }
+
+    /// once per cpu cycle
+    pub(crate) fn tick(&mut self) {
+        self.mapper.tick();
+    }
+
+    /// expansion audio, on the same scale as the apu mix
+    pub(crate) fn audio_output(&self) -> f32 {
+        self.mapper.audio_output()
+    }
+
+    /// names of the expansion audio channels, empty without any
+    pub fn audio_channels(&self) -> &'static [&'static str] {
+        self.mapper.audio_channels()
+    }
+
+    /// mute expansion audio channels, in the order of `audio_channels`
+    pub fn set_audio_channels(&mut self, enabled: &[bool]) {
+        self.mapper.set_audio_channels(enabled);
     }
 
     pub(crate) fn poll_irq(&mut self) -> bool {
@@ -153,20 +457,164 @@ impl Cartridge {
 
 #[allow(unused_variables)]
 pub trait Mapper {
-    fn read_prg(&self, prg: &[u8], addr: u16) -> u8;
+    /// where `addr` ($8000-$FFFF) lands in prg rom, if it is mapped to rom
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        None
+    }
+
+    fn read_prg(&self, prg: &[u8], addr: u16) -> u8 {
+        self.prg_offset(addr).map_or(0, |i| prg[i])
+    }
     fn write_prg(&mut self, prg: &mut [u8], addr: u16, data: u8) {}
 
+    /// registers some boards have at $4020-$5FFF, without side effects
+    fn peek_low(&self, addr: u16) -> Option<u8> {
+        None
+    }
+    /// like `peek_low` for a cpu read, which may e.g. acknowledge an irq
+    fn read_low(&mut self, addr: u16) -> Option<u8> {
+        self.peek_low(addr)
+    }
+    /// registers some boards have at $4020-$7FFF, called after the write reached ram
+    fn write_low(&mut self, addr: u16, data: u8) {}
+
+    /// where `addr` ($6000-$FFFF) lands in prg ram, `None` if it isn't ram
+    ///
+    /// 8 KB at $6000-$7FFF by default.
+    fn prg_ram_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x6000..=0x7fff => Some(addr as usize - 0x6000),
+            _ => None,
+        }
+    }
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        true
+    }
+    /// mapped ram that can't be read gives 0
+    fn prg_ram_readable(&self, addr: u16) -> bool {
+        true
+    }
+    /// prg ram the board has at least, for iNES headers which only know 8 KB
+    fn min_prg_ram_size(&self) -> usize {
+        0
+    }
+
+    /// `chr` is the rom, or the ram on boards without rom
     fn read_chr(&self, chr: &[u8], addr: u16) -> u8;
+    /// only called for chr ram
     fn write_chr(&mut self, chr: &mut [u8], addr: u16, data: u8) {
         chr[addr as usize] = data;
     }
+    /// where `addr` lands in chr ram on boards that have both rom and ram, `None` if it is rom
+    fn chr_ram_offset(&self, addr: u16) -> Option<usize> {
+        None
+    }
+    /// the prg rom is a flash the game writes its saves to
+    fn prg_flash(&self) -> bool {
+        false
+    }
+    /// whether the flash was written since the last poll
+    fn poll_flash_dirty(&mut self) -> bool {
+        false
+    }
+    /// chr ram the board has at least, for iNES headers which only know 8 KB
+    fn min_chr_ram_size(&self) -> usize {
+        0
+    }
+
+    /// offset of nametable `n` (0-3) in ciram, from `mirroring` by default
+    fn ciram_offset(&self, n: usize) -> usize {
+        MIRRORING_MAP[self.mirroring() as usize][n]
+    }
+    /// pattern table accesses ($0000-$1FFF) that go to ciram, as an offset into it
+    fn chr_ciram_offset(&self, addr: u16) -> Option<usize> {
+        None
+    }
+    /// nametable fetches ($2000-$2FFF) answered by the mapper instead of ciram,
+    /// `chr` is the chr rom for boards that can map it there
+    fn read_nametable(&self, chr: &[u8], addr: u16) -> Option<u8> {
+        None
+    }
+    /// true if the mapper took the write
+    fn write_nametable(&mut self, addr: u16, data: u8) -> bool {
+        false
+    }
+
+    /// called after each ppu read went through, e.g. for latches that switch chr banks
+    fn ppu_read(&mut self, addr: u16) {}
+    /// every address on the ppu bus, before the read if there is one. A12 watchers go here
+    fn ppu_address(&mut self, addr: u16) {}
+    /// cpu writes to the ppu registers ($2000-$2007), which some mappers watch
+    fn write_ppu_reg(&mut self, addr: u16, data: u8) {}
+
+    /// once per cpu cycle
+    fn tick(&mut self) {}
+    /// expansion audio, on the same scale as the apu mix
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+    fn audio_channels(&self) -> &'static [&'static str] {
+        &[]
+    }
+    fn set_audio_channels(&mut self, enabled: &[bool]) {}
 
-    fn update_scanline(&mut self) {}
     fn poll_irq(&mut self) -> bool {
         false
     }
 
     fn mirroring(&self) -> Mirroring;
+
+    fn save_state(&self, w: &mut StateWriter) {}
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        Ok(())
+    }
+}
+
+impl Snapshot for Cartridge {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(self.expansion.as_ref());
+        w.bytes(self.prg_ram.as_ref());
+        w.bytes(self.chr_ram.as_ref());
+        if self.mapper.prg_flash() {
+            w.bytes(self.prg_rom.as_ref());
+        }
+        self.mapper.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        r.bytes(self.expansion.as_mut())?;
+        r.bytes(self.prg_ram.as_mut())?;
+        self.sram_dirty = self.battery;
+        r.bytes(self.chr_ram.as_mut())?;
+        if self.mapper.prg_flash() {
+            r.bytes(self.prg_rom.as_mut())?;
+        }
+        self.mapper.load_state(r)
+    }
+}
+
+impl Snapshot for Mirroring {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(*self as u8);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        *self = match r.u8()? {
+            0 => Mirroring::Horizontal,
+            1 => Mirroring::Vertical,
+            2 => Mirroring::SingleScreen0,
+            3 => Mirroring::SingleScreen1,
+            4 => Mirroring::FourScreen,
+            _ => return Err(StateError::Invalid("mirroring")),
+        };
+        Ok(())
+    }
+}
+
+type BoxedMapper = Box<dyn Mapper + Send + Sync>;
+
+fn boxed(mapper: impl Mapper + Send + Sync + 'static) -> BoxedMapper {
+    Box::new(mapper)
 }
 
 struct NullMapper;
@@ -180,7 +628,7 @@ impl Mapper for NullMapper {
             0xff00 => 0x4c,
             0xff01 => 0x00,
             0xff02 => 0xff,
-            _ => unreachable!(),
+            _ => 0x00,
         }
     }
 
@@ -192,3 +640,827 @@ impl Mapper for NullMapper {
         Mirroring::FourScreen
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(mapper: u8, prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+        let mut data = vec![
+            b'N',
+            b'E',
+            b'S',
+            0x1a,
+            prg_banks,
+            chr_banks,
+            (mapper & 0x0f) << 4,
+            mapper & 0xf0,
+        ];
+        data.resize(HEADER_SIZE, 0);
+        data.resize(
+            HEADER_SIZE + prg_banks as usize * 0x4000 + chr_banks as usize * 0x2000,
+            0,
+        );
+        data
+    }
+
+    #[test]
+    fn test_load_errors() {
+        assert!(Cartridge::load(&rom(0, 2, 1)).is_ok());
+
+        assert_eq!(
+            Cartridge::load(&rom(0, 2, 1)[..8]).err(),
+            Some(LoadError::TruncatedHeader)
+        );
+        assert_eq!(
+            Cartridge::load(&[0u8; 0x20]).err(),
+            Some(LoadError::BadMagic)
+        );
+        assert_eq!(
+            Cartridge::load(&rom(0, 2, 1)[..0x6000]).err(),
+            Some(LoadError::TruncatedPrg {
+                expected: 0x8000,
+                found: 0x6000 - HEADER_SIZE
+            })
+        );
+        assert_eq!(
+            Cartridge::load(&rom(0, 4, 1)).err(),
+            Some(LoadError::InvalidBanks {
+                mapper: 0,
+                prg_banks: 4,
+                chr_banks: 1
+            })
+        );
+        assert_eq!(
+            Cartridge::load(&rom(15, 2, 1)).err(),
+            Some(LoadError::UnsupportedMapper(15))
+        );
+    }
+
+    #[test]
+    fn test_gxrom() {
+        // tag every 32 KB prg bank and 8 KB chr bank with its number
+        let mut data = rom(66, 8, 4);
+        for bank in 0..4 {
+            data[HEADER_SIZE + bank * 0x8000] = bank as u8;
+            data[HEADER_SIZE + 0x20000 + bank * 0x2000] = bank as u8;
+        }
+
+        data[HEADER_SIZE + 1] = 0xff;
+
+        let mut cart = Cartridge::load(&data).unwrap();
+        assert_eq!((cart.read(0x8000), cart.read_chr(0)), (0, 0));
+        cart.write(0x8001, 0b0010_0011);
+        assert_eq!((cart.read(0x8000), cart.read_chr(0)), (2, 3));
+        assert_eq!(cart.prg_offset(0x8001), Some(0x10001));
+    }
+
+    #[test]
+    fn test_mmc2_latches() {
+        // tag every 4 KB chr bank with its number
+        let mut data = rom(9, 8, 4);
+        for bank in 0..8 {
+            data[HEADER_SIZE + 0x20000 + bank * 0x1000] = bank as u8;
+        }
+
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0xb000, 1);
+        cart.write(0xc000, 2);
+        assert_eq!(cart.read_chr(0x0000), 2);
+        cart.ppu_read(0x0fd8);
+        assert_eq!(cart.read_chr(0x0000), 1);
+        // only the exact address on the left half of MMC2
+        cart.ppu_read(0x0fe9);
+        assert_eq!(cart.read_chr(0x0000), 1);
+        cart.ppu_read(0x0fe8);
+        assert_eq!(cart.read_chr(0x0000), 2);
+
+        cart.write(0xd000, 5);
+        cart.write(0xe000, 6);
+        cart.ppu_read(0x1fdf);
+        assert_eq!(cart.read_chr(0x1000), 5);
+
+        // a NES 2.0 header without prg ram leaves $6000 unmapped
+        data[7] |= 0x08;
+        let cart = Cartridge::load(&data).unwrap();
+        assert_eq!(cart.peek(0x6000), 0);
+    }
+
+    #[test]
+    fn test_axrom_bus_conflicts() {
+        let mut data = rom(7, 4, 0);
+        data[8] = 0x20; // submapper 2
+        data[7] |= 0x08; // nes 2.0
+        data[HEADER_SIZE + 0x10] = 0x01;
+        data[HEADER_SIZE + 0x8000 + 0x11] = 0x11;
+
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0x8000, 0x11);
+        assert_eq!(cart.prg_offset(0x8000), Some(0));
+        assert_eq!(cart.mirroring(), Mirroring::SingleScreen0);
+        cart.write(0x8010, 0x11);
+        assert_eq!(cart.prg_offset(0x8000), Some(0x8000));
+        cart.write(0x8011, 0x11);
+        assert_eq!(cart.mirroring(), Mirroring::SingleScreen1);
+    }
+
+    #[test]
+    fn test_mmc5() {
+        // tag every 8 KB prg bank with its number
+        let mut data = rom(5, 8, 1);
+        for bank in 0..16 {
+            data[HEADER_SIZE + bank * 0x2000] = bank as u8;
+        }
+
+        let mut cart = Cartridge::load(&data).unwrap();
+        // mode 3 at power on, $5117 is the last bank
+        assert_eq!(cart.read(0xe000), 15);
+        cart.write(0x5114, 0x83);
+        cart.write(0x5115, 0x85);
+        assert_eq!((cart.read(0x8000), cart.read(0xa000)), (3, 5));
+
+        // 16 KB banks ignore the low bit
+        cart.write(0x5100, 1);
+        assert_eq!((cart.read(0x8000), cart.read(0xa000)), (4, 5));
+
+        // ram in $8000-$BFFF, only writable once unprotected
+        cart.write(0x5115, 0x00);
+        assert_eq!(cart.prg_offset(0x8000), None);
+        cart.write(0x8000, 0x42);
+        assert_eq!(cart.read(0x8000), 0x00);
+        cart.write(0x5102, 0b10);
+        cart.write(0x5103, 0b01);
+        cart.write(0x8000, 0x42);
+        assert_eq!(cart.read(0x8000), 0x42);
+
+        // $5113 bit 7 doesn't put rom at $6000
+        cart.write(0x5113, 0x80);
+        cart.write(0x6000, 0x24);
+        assert_eq!(cart.read(0x6000), 0x24);
+
+        cart.write(0x5205, 200);
+        cart.write(0x5206, 100);
+        assert_eq!((cart.read(0x5205), cart.read(0x5206)), (0x20, 0x4e));
+
+        // ExRAM as plain ram in mode 2
+        cart.write(0x5104, 2);
+        cart.write(0x5c10, 0x33);
+        assert_eq!(cart.read(0x5c10), 0x33);
+
+        // 3 equal nametable fetches start a line, the irq fires on line 2
+        cart.write(0x5203, 2);
+        cart.write(0x5204, 0x80);
+        for _ in 0..2 {
+            for _ in 0..3 {
+                cart.ppu_read(0x2002);
+            }
+            assert!(!cart.poll_irq());
+            cart.ppu_read(0x0000);
+        }
+        for _ in 0..3 {
+            cart.ppu_read(0x2002);
+        }
+        assert!(cart.poll_irq());
+        assert_eq!(cart.read(0x5204), 0xc0);
+        assert!(!cart.poll_irq());
+    }
+
+    #[test]
+    fn test_vrc4() {
+        // tag every 8 KB prg bank with its number
+        let mut data = rom(25, 4, 1);
+        for bank in 0..8 {
+            data[HEADER_SIZE + bank * 0x2000] = bank as u8;
+        }
+        data[8] = 0x20; // submapper 2, VRC4d
+        data[7] |= 0x08;
+
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0x8000, 3);
+        assert_eq!((cart.read(0x8000), cart.read(0xc000)), (3, 6));
+        // A3 and A2 select the register, $9004 is the prg swap mode
+        cart.write(0x9004, 0b10);
+        assert_eq!((cart.read(0x8000), cart.read(0xc000)), (6, 3));
+        cart.write(0x9000, 2);
+        assert_eq!(cart.mirroring(), Mirroring::SingleScreen0);
+
+        // cycle mode, fires on the overflow from $FF
+        cart.write(0xf000, 0x0d);
+        cart.write(0xf008, 0x0f);
+        cart.write(0xf004, 0b110);
+        for _ in 0..2 {
+            assert!(!cart.poll_irq());
+            cart.tick();
+        }
+        cart.tick();
+        assert!(cart.poll_irq());
+        cart.write(0xf00c, 0);
+        assert!(!cart.poll_irq());
+    }
+
+    #[test]
+    fn test_vrc6() {
+        let mut data = rom(26, 8, 1);
+        for bank in 0..16 {
+            data[HEADER_SIZE + bank * 0x2000] = bank as u8;
+        }
+
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0x8000, 2);
+        cart.write(0xc000, 5);
+        assert_eq!((cart.read(0x8000), cart.read(0xc000)), (4, 5));
+        assert_eq!(cart.read(0xe000), 15);
+
+        // constant volume on pulse 1, A0 and A1 are swapped on 026
+        assert_eq!(cart.audio_channels().len(), 3);
+        cart.write(0x9000, 0x8f);
+        cart.write(0x9001, 0x80);
+        cart.tick();
+        assert!(cart.audio_output() > 0.0);
+        cart.set_audio_channels(&[false, true, true]);
+        cart.tick();
+        assert_eq!(cart.audio_output(), 0.0);
+    }
+
+    #[test]
+    fn test_fme7() {
+        let mut data = rom(69, 4, 1);
+        for bank in 0..8 {
+            data[HEADER_SIZE + bank * 0x2000] = bank as u8;
+        }
+
+        let mut cart = Cartridge::load(&data).unwrap();
+        assert_eq!(cart.read(0xe000), 7);
+        cart.write(0x8000, 0x9);
+        cart.write(0xa000, 5);
+        assert_eq!(cart.read(0x8000), 5);
+
+        // $6000 is rom until ram is selected and enabled
+        cart.write(0x8000, 0x8);
+        cart.write(0xa000, 3);
+        assert_eq!(cart.read(0x6000), 3);
+        cart.write(0xa000, 0xc0);
+        cart.write(0x6000, 0x42);
+        assert_eq!(cart.read(0x6000), 0x42);
+
+        // counts down every cycle, fires on the wrap to $FFFF
+        cart.write(0x8000, 0xe);
+        cart.write(0xa000, 2);
+        cart.write(0x8000, 0xf);
+        cart.write(0xa000, 0);
+        cart.write(0x8000, 0xd);
+        cart.write(0xa000, 0x81);
+        for _ in 0..2 {
+            cart.tick();
+            assert!(!cart.poll_irq());
+        }
+        cart.tick();
+        assert!(cart.poll_irq());
+        cart.write(0xa000, 0x81);
+        assert!(!cart.poll_irq());
+
+        // tone a at full volume
+        assert_eq!(cart.audio_channels().len(), 3);
+        cart.write(0xc000, 0x07);
+        cart.write(0xe000, 0x3e);
+        cart.write(0xc000, 0x08);
+        cart.write(0xe000, 0x0f);
+        // the square goes high on its first tone clock
+        for _ in 0..16 {
+            cart.tick();
+        }
+        assert!(cart.audio_output() > 0.0);
+        cart.set_audio_channels(&[false, true, true]);
+        cart.tick();
+        assert_eq!(cart.audio_output(), 0.0);
+    }
+
+    #[test]
+    fn test_n163() {
+        let mut data = rom(19, 4, 1);
+        for bank in 0..8 {
+            data[HEADER_SIZE + bank * 0x2000] = bank as u8;
+        }
+        let chr = HEADER_SIZE + 4 * 0x4000;
+        for bank in 0..8 {
+            data[chr + bank * 0x400] = 0x10 + bank as u8;
+        }
+
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0xe000, 2);
+        cart.write(0xf000, 5);
+        assert_eq!((cart.read(0x8000), cart.read(0xc000)), (2, 5));
+        assert_eq!(cart.read(0xe000), 7);
+
+        // nametables from chr rom or ciram, pattern tables from ciram too
+        cart.write(0xc800, 3);
+        cart.write(0xd000, 0xe1);
+        assert_eq!(cart.read_nametable(0x2400), Some(0x13));
+        assert_eq!(cart.read_nametable(0x2800), None);
+        assert_eq!(cart.nm_addr(0x2800), 0x400);
+        cart.write(0x8800, 0xe1);
+        assert_eq!(cart.chr_ciram_addr(0x0400), Some(0x400));
+        cart.write(0xe800, 0x40);
+        assert_eq!(cart.chr_ciram_addr(0x0400), None);
+
+        // counts up to $7FFF
+        cart.write(0x5000, 0xfd);
+        cart.write(0x5800, 0xff);
+        for _ in 0..2 {
+            assert!(!cart.poll_irq());
+            cart.tick();
+        }
+        assert!(cart.poll_irq());
+        assert_eq!(cart.read(0x5800), 0xff);
+        cart.write(0x5800, 0);
+        assert!(!cart.poll_irq());
+
+        // sound ram through the data port
+        cart.write(0xf800, 0x80);
+        cart.write(0x4800, 0x12);
+        cart.write(0x4800, 0x34);
+        cart.write(0xf800, 0x80);
+        assert_eq!((cart.read(0x4800), cart.read(0x4800)), (0x12, 0x34));
+
+        // channel 1, a wave of 4 samples at 0x00 reading 2, 1, 4, 3
+        assert_eq!(cart.audio_channels().len(), 8);
+        cart.write(0xf800, 0xfc);
+        for data in [0xfc, 0x00, 0x00, 0x0f] {
+            cart.write(0x4800, data);
+        }
+        for _ in 0..15 {
+            cart.tick();
+        }
+        assert!(cart.audio_output() < 0.0);
+        cart.set_audio_channels(&[false; 8]);
+        for _ in 0..15 {
+            cart.tick();
+        }
+        assert_eq!(cart.audio_output(), 0.0);
+    }
+
+    #[test]
+    fn test_mmc3_variants() {
+        // a rise of A12 after it was low for `low` cpu cycles
+        fn a12_rise(cart: &mut Cartridge, low: usize) {
+            cart.ppu_address(0x0ff0);
+            for _ in 0..low {
+                cart.tick();
+            }
+            cart.ppu_address(0x1000);
+        }
+        fn scanlines(cart: &mut Cartridge, n: usize) -> Vec<bool> {
+            (0..n)
+                .map(|_| {
+                    a12_rise(cart, 3);
+                    cart.poll_irq()
+                })
+                .collect()
+        }
+
+        // a latch of 0 fires on every line on MMC3C, only after $C001 on MMC3A
+        let mut data = rom(4, 2, 1);
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0xc000, 0);
+        cart.write(0xc001, 0);
+        cart.write(0xe001, 0);
+        assert_eq!(scanlines(&mut cart, 3), [true; 3]);
+        data[7] |= 0x08;
+        data[8] = 0x40;
+        data[10] = 0x07; // 8 KB prg ram
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0xc000, 0);
+        cart.write(0xc001, 0);
+        cart.write(0xe001, 0);
+        assert_eq!(scanlines(&mut cart, 3), [true, false, false]);
+
+        // rises after a short low are filtered out
+        cart.write(0xc000, 1);
+        cart.write(0xc001, 0);
+        assert_eq!(scanlines(&mut cart, 1), [false]);
+        a12_rise(&mut cart, 1);
+        assert!(!cart.poll_irq());
+        a12_rise(&mut cart, 3);
+        assert!(cart.poll_irq());
+
+        // $A001 write protect and disable
+        cart.write(0x6000, 1);
+        cart.write(0xa001, 0xc0);
+        cart.write(0x6000, 2);
+        assert_eq!(cart.read(0x6000), 1);
+        cart.write(0xa001, 0x00);
+        assert_eq!(cart.read(0x6000), 0);
+
+        // MMC6, each half readable and writable on its own
+        data[8] = 0x10;
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0x8000, 0x20);
+        cart.write(0xa001, 0xf0);
+        cart.write(0x7000, 1);
+        cart.write(0x7200, 2);
+        assert_eq!((cart.read(0x7400), cart.read(0x7600)), (1, 2));
+        // bit 5 and 7 enable reads, 4 and 6 writes
+        cart.write(0xa001, 0xb0);
+        cart.write(0x7200, 3);
+        assert_eq!(cart.read(0x7200), 2);
+        cart.write(0xa001, 0x10);
+        cart.write(0x7000, 5);
+        assert_eq!(cart.read(0x7000), 0);
+        cart.write(0xa001, 0x20);
+        assert_eq!((cart.read(0x7000), cart.read(0x7200)), (5, 0));
+        assert_eq!(cart.read(0x6000), 0);
+
+        // TxSROM, chr bank bit 7 picks the ciram page
+        let mut cart = Cartridge::load(&rom(118, 2, 1)).unwrap();
+        cart.write(0x8000, 0);
+        cart.write(0x8001, 0x80);
+        assert_eq!(cart.nm_addr(0x2000), 0x400);
+        assert_eq!(cart.nm_addr(0x2800), 0);
+        cart.write(0x8000, 1);
+        cart.write(0x8001, 0x80);
+        assert_eq!(cart.mirroring(), Mirroring::SingleScreen1);
+        cart.write(0xa000, 0);
+        assert_eq!(cart.mirroring(), Mirroring::SingleScreen1);
+
+        // TQROM, bit 6 switches to chr ram
+        let mut data = rom(119, 2, 1);
+        data[HEADER_SIZE + 2 * 0x4000 + 0x400] = 0x55;
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0x8000, 2);
+        cart.write(0x8001, 1);
+        assert_eq!(cart.read_chr(0x1000), 0x55);
+        cart.write_chr(0x1000, 0xaa);
+        assert_eq!(cart.read_chr(0x1000), 0x55);
+        cart.write(0x8001, 0x41);
+        cart.write_chr(0x1000, 0xaa);
+        assert_eq!(cart.read_chr(0x1000), 0xaa);
+    }
+
+    #[test]
+    fn test_ram_sizes() {
+        // SXROM, 32 KB of prg ram banked by chr bank 0 bits 2-3
+        let mut data = rom(1, 2, 0);
+        data[7] |= 0x08;
+        data[10] = 0x90; // 32 KB prg nvram
+        data[11] = 0x07; // 8 KB chr ram
+        let mut cart = Cartridge::load(&data).unwrap();
+        assert_eq!(cart.prg_ram_size(), 0x8000);
+        let write_mmc1 = |cart: &mut Cartridge, addr: u16, data: u8| {
+            for i in 0..5 {
+                cart.write(addr, data >> i & 1);
+            }
+        };
+        for bank in 0..4 {
+            write_mmc1(&mut cart, 0xa000, bank << 2);
+            cart.write(0x6000, bank);
+        }
+        write_mmc1(&mut cart, 0xa000, 2 << 2);
+        assert_eq!(cart.read(0x6000), 2);
+        assert_eq!(cart.prg_ram[0x6000], 3);
+
+        // no prg ram at all
+        data[10] = 0;
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0x6000, 1);
+        assert_eq!(cart.read(0x6000), 0);
+
+        // UNROM-512, 32 KB of chr ram even from an iNES header
+        let mut cart = Cartridge::load(&rom(30, 32, 0)).unwrap();
+        assert_eq!(cart.chr_ram_size(), 0x8000);
+        cart.write(0x8000, 0x60 | 5);
+        cart.write_chr(0x0010, 0x55);
+        assert_eq!(cart.chr_ram[0x6010], 0x55);
+        cart.write(0x8000, 0);
+        assert_eq!(cart.read_chr(0x0010), 0);
+
+        // CPROM, 16 KB with the lower 4 KB fixed
+        let mut cart = Cartridge::load(&rom(13, 2, 0)).unwrap();
+        assert_eq!(cart.chr_ram_size(), 0x4000);
+        cart.write(0x8000, 3);
+        cart.write_chr(0x1000, 0xaa);
+        cart.write_chr(0x0000, 0xbb);
+        assert_eq!((cart.chr_ram[0x3000], cart.chr_ram[0]), (0xaa, 0xbb));
+    }
+
+    #[test]
+    fn test_unrom512_flash() {
+        let mut data = rom(30, 32, 0);
+        data[6] |= 0x02;
+        let mut cart = Cartridge::load(&data).unwrap();
+
+        // the unlock addresses are $5555 and $2AAA in the flash
+        let command = |cart: &mut Cartridge, data: u8| {
+            cart.write(0xc000, 1);
+            cart.write(0x9555, 0xaa);
+            cart.write(0xc000, 0);
+            cart.write(0xaaaa, 0x55);
+            cart.write(0xc000, 1);
+            cart.write(0x9555, data);
+        };
+
+        command(&mut cart, 0x90);
+        assert_eq!((cart.read(0x8000), cart.read(0xc001)), (0xbf, 0xb7));
+        cart.write(0x8000, 0xf0);
+        assert_eq!(cart.read(0x8000), 0);
+
+        // erase the sector at $8000 of bank 2, then program a byte in it
+        command(&mut cart, 0x80);
+        cart.write(0xc000, 1);
+        cart.write(0x9555, 0xaa);
+        cart.write(0xc000, 0);
+        cart.write(0xaaaa, 0x55);
+        cart.write(0xc000, 2);
+        cart.write(0x8123, 0x30);
+        assert_eq!((cart.read(0x8000), cart.read(0x8fff)), (0xff, 0xff));
+        assert_eq!(cart.read(0x9000), 0);
+        command(&mut cart, 0xa0);
+        cart.write(0xc000, 2);
+        cart.write(0x8123, 0x5a);
+        assert_eq!(cart.read(0x8123), 0x5a);
+
+        assert!(cart.poll_sram_dirty());
+        assert!(!cart.poll_sram_dirty());
+        let save = cart.sram().unwrap().to_vec();
+        assert_eq!(save[0x8123], 0x5a);
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.load_sram(&save);
+        cart.write(0xc000, 2);
+        assert_eq!(cart.read(0x8123), 0x5a);
+
+        // without the battery bit the rom can't be written
+        data[6] &= !0x02;
+        let mut cart = Cartridge::load(&data).unwrap();
+        assert!(cart.sram().is_none());
+        cart.write(0x8000, 3);
+        assert_eq!(cart.read(0x8000), 0);
+    }
+
+    #[test]
+    fn test_bus_conflicts() {
+        let mut data = rom(2, 4, 0);
+        for bank in 0..4 {
+            data[HEADER_SIZE + bank * 0x4000] = bank as u8;
+            data[HEADER_SIZE + bank * 0x4000 + 1] = 0xff;
+        }
+
+        // the rom byte at $C000 is 3, at $C001 $FF
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0xc000, 2);
+        assert_eq!(cart.read(0x8000), 2);
+        data[7] |= 0x08;
+        data[8] = 0x20;
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0xc000, 0x04);
+        assert_eq!(cart.read(0x8000), 0);
+        cart.write(0xc000, 0x06);
+        assert_eq!(cart.read(0x8000), 2);
+        cart.write(0xc001, 0x01);
+        assert_eq!(cart.read(0x8000), 1);
+
+        // CNROM, all zero rom
+        let mut data = rom(3, 2, 4);
+        data[7] |= 0x08;
+        data[8] = 0x20;
+        let chr = HEADER_SIZE + 2 * 0x4000;
+        for bank in 0..4 {
+            data[chr + bank * 0x2000] = bank as u8;
+        }
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0x8000, 3);
+        assert_eq!(cart.read_chr(0), 0);
+        data[8] = 0x10;
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0x8000, 3);
+        assert_eq!(cart.read_chr(0), 3);
+
+        // Color Dreams and GxROM always have them, $8000 is 0 and $8001 $FF in every bank
+        for (mapper, value) in [(11, 0x11), (66, 0x11)] {
+            let mut data = rom(mapper, 4, 2);
+            for bank in 0..2 {
+                data[HEADER_SIZE + bank * 0x8000 + 1] = 0xff;
+                data[HEADER_SIZE + bank * 0x8000 + 2] = bank as u8;
+                data[HEADER_SIZE + 4 * 0x4000 + bank * 0x2000] = bank as u8;
+            }
+            let mut cart = Cartridge::load(&data).unwrap();
+            cart.write(0x8000, value);
+            assert_eq!((cart.read(0x8002), cart.read_chr(0)), (0, 0));
+            cart.write(0x8001, value);
+            assert_eq!((cart.read(0x8002), cart.read_chr(0)), (1, 1));
+        }
+    }
+
+    /// a rom with each 8 KB prg bank and each 1 KB chr bank starting with its number
+    fn numbered_rom(mapper: u8, prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+        let mut data = rom(mapper, prg_banks, chr_banks);
+        for bank in 0..prg_banks as usize * 2 {
+            data[HEADER_SIZE + bank * 0x2000] = bank as u8;
+        }
+        let chr = HEADER_SIZE + prg_banks as usize * 0x4000;
+        for bank in 0..chr_banks as usize * 8 {
+            data[chr + bank * 0x400] = bank as u8;
+        }
+        data
+    }
+
+    /// save `cart`, overwrite the first `words` usizes of the mapper state with `value` and load it
+    fn load_corrupt_state(cart: &mut Cartridge, words: usize, value: u64) {
+        let mut w = StateWriter::new(cart.hash());
+        cart.save_state(&mut w);
+        let mut state = w.finish();
+
+        // the state header, then expansion rom, prg and chr ram
+        let start = 12 + EXPANSION_ROM_SIZE + cart.prg_ram_size() + cart.chr_ram_size();
+        for word in state[start..][..words * 8].chunks_mut(8) {
+            word.copy_from_slice(&value.to_le_bytes());
+        }
+        let mut r = StateReader::new(&state, cart.hash()).unwrap();
+        cart.load_state(&mut r).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_state_banks() {
+        let mut data = rom(2, 4, 0);
+        for bank in 0..4 {
+            data[HEADER_SIZE + bank * 0x4000] = bank as u8;
+        }
+        // an out of range UxROM bank wraps
+        let mut cart = Cartridge::load(&data).unwrap();
+        load_corrupt_state(&mut cart, 1, 6);
+        assert_eq!(cart.read(0x8000), 2);
+
+        // MMC1 prg and chr banks
+        let mut cart = Cartridge::load(&rom(1, 4, 2)).unwrap();
+        load_corrupt_state(&mut cart, 4, 0x0123_4567_89ab_cdef);
+        cart.read(0x8000);
+        cart.read(0xc000);
+        cart.read_chr(0x0000);
+        cart.read_chr(0x1000);
+
+        // MMC3 prg and chr banks
+        let mut cart = Cartridge::load(&rom(4, 4, 2)).unwrap();
+        load_corrupt_state(&mut cart, 12, 0x0123_4567_89ab_cdef);
+        for addr in (0x8000..=0xe000).step_by(0x2000) {
+            cart.read(addr);
+        }
+        for addr in (0x0000..0x2000).step_by(0x400) {
+            cart.read_chr(addr);
+        }
+    }
+
+    #[test]
+    fn test_irem_g101() {
+        let mut cart = Cartridge::load(&numbered_rom(32, 4, 1)).unwrap();
+        assert_eq!(cart.read(0xc000), 6);
+        assert_eq!(cart.read(0xe000), 7);
+        cart.write(0x8000, 3);
+        cart.write(0xa000, 4);
+        cart.write(0xb005, 2);
+        assert_eq!(cart.read(0x8000), 3);
+        assert_eq!(cart.read(0xa000), 4);
+        assert_eq!(cart.read_chr(0x1400), 2);
+
+        cart.write(0x9000, 0b11);
+        assert_eq!(cart.read(0x8000), 6);
+        assert_eq!(cart.read(0xc000), 3);
+        assert_eq!(cart.mirroring(), Mirroring::Horizontal);
+
+        // Major League ignores $9000
+        let mut data = numbered_rom(32, 4, 1);
+        data[7] |= 0x08;
+        data[8] = 0x10;
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0x9000, 0b11);
+        assert_eq!(cart.mirroring(), Mirroring::SingleScreen0);
+        assert_eq!(cart.read(0xc000), 6);
+    }
+
+    #[test]
+    fn test_camerica() {
+        let mut cart = Cartridge::load(&numbered_rom(71, 4, 0)).unwrap();
+        assert_eq!(cart.read(0xc000), 6);
+        cart.write(0xc000, 2);
+        assert_eq!(cart.read(0x8000), 4);
+
+        // only Fire Hawk writes $9000, which turns on the single screen control
+        cart.write(0x8000, 0x10);
+        assert_eq!(cart.mirroring(), Mirroring::Horizontal);
+        cart.write(0x9000, 0x10);
+        assert_eq!(cart.mirroring(), Mirroring::SingleScreen1);
+        cart.write(0x8000, 0x00);
+        assert_eq!(cart.mirroring(), Mirroring::SingleScreen0);
+    }
+
+    #[test]
+    fn test_namco108() {
+        let mut cart = Cartridge::load(&numbered_rom(206, 4, 2)).unwrap();
+        assert_eq!(cart.read(0xc000), 6);
+        assert_eq!(cart.read(0xe000), 7);
+        cart.write(0x8000, 6);
+        cart.write(0x8001, 3);
+        cart.write(0x8000, 7);
+        cart.write(0x8001, 5);
+        assert_eq!(cart.read(0x8000), 3);
+        assert_eq!(cart.read(0xa000), 5);
+
+        // 2 KB banks ignore the low bit
+        cart.write(0x8000, 1);
+        cart.write(0x8001, 5);
+        assert_eq!(cart.read_chr(0x0800), 4);
+        assert_eq!(cart.read_chr(0x0c00), 5);
+        cart.write(0x8000, 5);
+        cart.write(0x8001, 9);
+        assert_eq!(cart.read_chr(0x1c00), 9);
+
+        // no registers past $9FFF
+        cart.write(0xa000, 1);
+        cart.write(0xe000, 0);
+        cart.write(0x8001, 2);
+        assert_eq!(cart.read_chr(0x1c00), 2);
+    }
+
+    #[test]
+    fn test_mapper225() {
+        let mut cart = Cartridge::load(&numbered_rom(225, 4, 2)).unwrap();
+        cart.write(0x8000 | 1 << 6 | 1, 0);
+        assert_eq!(cart.read(0x8000), 0);
+        assert_eq!(cart.read(0xc000), 2);
+        assert_eq!(cart.read_chr(0), 8);
+        assert_eq!(cart.mirroring(), Mirroring::Vertical);
+
+        cart.write(0xb000 | 3 << 6, 0);
+        assert_eq!(cart.read(0x8000), 6);
+        assert_eq!(cart.read(0xc000), 6);
+        assert_eq!(cart.mirroring(), Mirroring::Horizontal);
+
+        cart.write(0x5800, 0xff);
+        assert_eq!(cart.read(0x5804), 0x0f);
+    }
+
+    #[test]
+    fn test_mapper226() {
+        let mut cart = Cartridge::load(&numbered_rom(226, 4, 0)).unwrap();
+        cart.write(0x8000, 0x03);
+        assert_eq!(cart.read(0x8000), 4);
+        assert_eq!(cart.read(0xc000), 6);
+        assert_eq!(cart.mirroring(), Mirroring::Horizontal);
+
+        cart.write(0x8000, 0x61);
+        assert_eq!(cart.read(0x8000), 2);
+        assert_eq!(cart.read(0xc000), 2);
+        assert_eq!(cart.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_mapper227() {
+        let mut cart = Cartridge::load(&numbered_rom(227, 16, 0)).unwrap();
+        // UNROM like, $C000 fixed to the start of the 128 KB block
+        cart.write(0x8000 | 9 << 2, 0);
+        assert_eq!(cart.read(0x8000), 18);
+        assert_eq!(cart.read(0xc000), 16);
+        cart.write(0x8200 | 9 << 2, 0);
+        assert_eq!(cart.read(0xc000), 30);
+
+        // NROM-256
+        cart.write(0x8080 | 9 << 2 | 0b11, 0);
+        assert_eq!(cart.read(0x8000), 16);
+        assert_eq!(cart.read(0xc000), 18);
+        assert_eq!(cart.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_action52() {
+        let mut cart = Cartridge::load(&numbered_rom(228, 8, 8)).unwrap();
+        cart.write(0x8000 | 3 << 6 | 1 << 5 | 0x1, 0b10);
+        assert_eq!(cart.read(0x8000), 6);
+        assert_eq!(cart.read(0xc000), 6);
+        assert_eq!(cart.read_chr(0), 6 * 8);
+
+        cart.write(0xa000 | 3 << 6, 0);
+        assert_eq!(cart.read(0x8000), 4);
+        assert_eq!(cart.read(0xc000), 6);
+        assert_eq!(cart.mirroring(), Mirroring::Horizontal);
+
+        cart.write(0x4020, 0xa5);
+        assert_eq!(cart.read(0x5ffc), 0x05);
+
+        // chip 3 follows chip 1 in the rom
+        let mut data = rom(228, 96, 1);
+        data[HEADER_SIZE + 64 * 0x4000] = 0xc3;
+        let mut cart = Cartridge::load(&data).unwrap();
+        cart.write(0x9800, 0);
+        assert_eq!(cart.read(0x8000), 0xc3);
+    }
+
+    #[test]
+    fn test_mapper231() {
+        let mut cart = Cartridge::load(&numbered_rom(231, 8, 0)).unwrap();
+        cart.write(0x8000 | 3 << 1, 0);
+        assert_eq!(cart.read(0x8000), 12);
+        assert_eq!(cart.read(0xc000), 12);
+        assert_eq!(cart.mirroring(), Mirroring::Vertical);
+
+        cart.write(0x80a0 | 3 << 1, 0);
+        assert_eq!(cart.read(0xc000), 14);
+        assert_eq!(cart.mirroring(), Mirroring::Horizontal);
+    }
+}
@@ -0,0 +1,232 @@
+use super::Mirroring;
+use bit_field::BitField;
+
+pub const HEADER_SIZE: usize = 0x10;
+pub const TRAINER_SIZE: usize = 0x200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderFormat {
+    INes,
+    Nes20,
+}
+
+/// CPU/PPU timing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem { ppu: u8, hardware: u8 },
+    Playchoice10,
+    Extended(u8),
+}
+
+/// everything the rom header tells about a cartridge, sizes are in bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CartInfo {
+    pub format: HeaderFormat,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+
+    pub timing: Timing,
+    pub console: ConsoleType,
+    pub misc_roms: u8,
+    pub expansion_device: u8,
+}
+
+impl CartInfo {
+    /// parse an iNES or NES 2.0 header, `None` if the magic doesn't match
+    pub fn parse(header: &[u8; HEADER_SIZE]) -> Option<Self> {
+        if header[..4] != [b'N', b'E', b'S', 0x1a] {
+            return None;
+        }
+
+        let f6 = header[6];
+        let f7 = header[7];
+        let mirroring = if f6.get_bit(3) {
+            Mirroring::FourScreen
+        } else if f6.get_bit(0) {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        let battery = f6.get_bit(1);
+        let trainer = f6.get_bit(2);
+
+        let info = if f7.get_bits(2..4) == 0b10 {
+            let console = match f7.get_bits(0..2) {
+                0 => ConsoleType::Nes,
+                1 => ConsoleType::VsSystem {
+                    ppu: header[13].get_bits(0..4),
+                    hardware: header[13].get_bits(4..8),
+                },
+                2 => ConsoleType::Playchoice10,
+                _ => ConsoleType::Extended(header[13].get_bits(0..4)),
+            };
+
+            CartInfo {
+                format: HeaderFormat::Nes20,
+                mapper: (f6 >> 4) as u16
+                    | (f7 & 0xf0) as u16
+                    | (header[8].get_bits(0..4) as u16) << 8,
+                submapper: header[8].get_bits(4..8),
+                mirroring,
+                battery,
+                trainer,
+
+                prg_rom_size: rom_size(header[4], header[9].get_bits(0..4), 0x4000),
+                chr_rom_size: rom_size(header[5], header[9].get_bits(4..8), 0x2000),
+                prg_ram_size: ram_size(header[10].get_bits(0..4)),
+                prg_nvram_size: ram_size(header[10].get_bits(4..8)),
+                chr_ram_size: ram_size(header[11].get_bits(0..4)),
+                chr_nvram_size: ram_size(header[11].get_bits(4..8)),
+
+                timing: match header[12].get_bits(0..2) {
+                    0 => Timing::Ntsc,
+                    1 => Timing::Pal,
+                    2 => Timing::MultiRegion,
+                    _ => Timing::Dendy,
+                },
+                console,
+                misc_roms: header[14].get_bits(0..2),
+                expansion_device: header[15].get_bits(0..6),
+            }
+        } else {
+            // old dumps may have garbage like "DiskDude!" in bytes 7-15
+            let archaic = header[12..].iter().any(|&b| b != 0);
+            let mapper = if archaic {
+                (f6 >> 4) as u16
+            } else {
+                (f6 >> 4) as u16 | (f7 & 0xf0) as u16
+            };
+
+            let chr_rom_size = header[5] as usize * 0x2000;
+            let ram_size = (header[8].max(1) as usize) * 0x2000;
+
+            CartInfo {
+                format: HeaderFormat::INes,
+                mapper,
+                submapper: 0,
+                mirroring,
+                battery,
+                trainer,
+
+                prg_rom_size: header[4] as usize * 0x4000,
+                chr_rom_size,
+                prg_ram_size: if battery { 0 } else { ram_size },
+                prg_nvram_size: if battery { ram_size } else { 0 },
+                chr_ram_size: if chr_rom_size == 0 { 0x2000 } else { 0 },
+                chr_nvram_size: 0,
+
+                timing: if !archaic && header[9].get_bit(0) {
+                    Timing::Pal
+                } else {
+                    Timing::Ntsc
+                },
+                console: if archaic {
+                    ConsoleType::Nes
+                } else {
+                    match f7.get_bits(0..2) {
+                        0 => ConsoleType::Nes,
+                        1 => ConsoleType::VsSystem {
+                            ppu: 0,
+                            hardware: 0,
+                        },
+                        _ => ConsoleType::Playchoice10,
+                    }
+                },
+                misc_roms: 0,
+                expansion_device: 0,
+            }
+        };
+
+        Some(info)
+    }
+}
+
+/// NES 2.0 rom size, either in `unit` sized pages or `2^E * (MM * 2 + 1)` bytes
+fn rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+    if msb == 0x0f {
+        let exp = lsb.get_bits(2..8) as u32;
+        let mul = lsb.get_bits(0..2) as usize * 2 + 1;
+        2usize.saturating_pow(exp).saturating_mul(mul)
+    } else {
+        (((msb as usize) << 8) | lsb as usize) * unit
+    }
+}
+
+/// NES 2.0 ram size, `64 << shift` bytes or none
+fn ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ines() {
+        let header = *b"NES\x1a\x02\x01\x13\x40\x00\x00\x00\x00\x00\x00\x00\x00";
+        let info = CartInfo::parse(&header).unwrap();
+        assert_eq!(info.format, HeaderFormat::INes);
+        assert_eq!(info.mapper, 0x41);
+        assert_eq!(info.mirroring, Mirroring::Vertical);
+        assert!(info.battery);
+        assert_eq!(info.prg_rom_size, 0x8000);
+        assert_eq!(info.chr_rom_size, 0x2000);
+        assert_eq!(info.prg_nvram_size, 0x2000);
+
+        let header = *b"NES\x1a\x02\x00\x10DiskDude!";
+        let info = CartInfo::parse(&header).unwrap();
+        assert_eq!(info.mapper, 1);
+        assert_eq!(info.chr_ram_size, 0x2000);
+    }
+
+    #[test]
+    fn test_nes20() {
+        let header = [
+            b'N', b'E', b'S', 0x1a, 0x20, 0x34, 0x42, 0x19, 0x51, 0xf0, 0x70, 0x07, 0x01, 0x00,
+            0x00, 0x03,
+        ];
+        let info = CartInfo::parse(&header).unwrap();
+        assert_eq!(info.format, HeaderFormat::Nes20);
+        assert_eq!(info.mapper, 0x114);
+        assert_eq!(info.submapper, 5);
+        assert_eq!(info.prg_rom_size, 0x20 * 0x4000);
+        assert_eq!(info.chr_rom_size, 0x2000);
+        assert_eq!(info.prg_ram_size, 0);
+        assert_eq!(info.prg_nvram_size, 0x2000);
+        assert_eq!(info.chr_ram_size, 0x2000);
+        assert_eq!(info.timing, Timing::Pal);
+        assert_eq!(
+            info.console,
+            ConsoleType::VsSystem {
+                ppu: 0,
+                hardware: 0
+            }
+        );
+        assert_eq!(info.expansion_device, 3);
+
+        assert!(CartInfo::parse(&[0u8; HEADER_SIZE]).is_none());
+    }
+}
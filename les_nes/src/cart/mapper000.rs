@@ -10,26 +10,24 @@ pub struct Mapper000 {
 }
 
 impl Mapper000 {
-    pub fn new(mirroring: Mirroring, prg_banks: usize) -> Self {
-        assert!(
-            prg_banks == 1 || prg_banks == 2,
-            "invalid banks for Mapper000, prg: {}",
-            prg_banks
-        );
+    pub fn new(mirroring: Mirroring, prg_banks: usize) -> Option<Self> {
+        if !(prg_banks == 1 || prg_banks == 2) {
+            return None;
+        }
 
-        Self {
+        Some(Self {
             prg_bank1: prg_banks - 1,
             mirroring,
-        }
+        })
     }
 }
 
 impl super::Mapper for Mapper000 {
-    fn read_prg(&self, prg: &[u8], addr: u16) -> u8 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
         match addr {
-            0x8000..=0xbfff => prg[addr as usize - 0x8000],
-            0xc000..=0xffff => prg[addr as usize - 0xc000 + self.prg_bank1 * 0x4000],
-            _ => unreachable!(),
+            0x8000..=0xbfff => Some(addr as usize - 0x8000),
+            0xc000..=0xffff => Some(addr as usize - 0xc000 + self.prg_bank1 * 0x4000),
+            _ => None,
         }
     }
 
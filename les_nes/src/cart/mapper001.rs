@@ -1,4 +1,5 @@
 use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
 use bit_field::BitField;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -26,13 +27,19 @@ pub struct Mapper001 {
     prg_mode: PRGMode,
     chr_mode: CHRMode,
     enable_ram: bool,
+    /// 8 KB prg ram banks, 2 on SOROM and 4 on SXROM picked by the chr bank 0 register
+    ram_banks: usize,
 
     mirroring: Mirroring,
 }
 
 impl Mapper001 {
-    pub fn new(mirroring: Mirroring, prg_banks: usize) -> Self {
-        Self {
+    pub fn new(mirroring: Mirroring, prg_banks: usize, prg_ram_size: usize) -> Option<Self> {
+        if prg_banks == 0 {
+            return None;
+        }
+
+        Some(Self {
             prg_bank0: 0,
             prg_bank1: prg_banks - 1,
             prg_banks,
@@ -44,9 +51,10 @@ impl Mapper001 {
             prg_mode: PRGMode::FixedLast,
             chr_mode: CHRMode::Full,
             enable_ram: false,
+            ram_banks: prg_ram_size.div_ceil(0x2000).max(1),
 
             mirroring,
-        }
+        })
     }
 
     pub fn handle_write(&mut self, addr: u16) {
@@ -80,15 +88,15 @@ impl Mapper001 {
             0xe000..=0xffff => {
                 match self.prg_mode {
                     PRGMode::Full => {
-                        self.prg_bank0 = bank_bits & 0b01110;
-                        self.prg_bank1 = self.prg_bank0 + 1;
+                        self.prg_bank0 = (bank_bits & 0b01110) % self.prg_banks;
+                        self.prg_bank1 = (self.prg_bank0 + 1) % self.prg_banks;
                     }
                     PRGMode::FixedFirst => {
                         self.prg_bank0 = 0;
-                        self.prg_bank1 = bank_bits & 0b01111;
+                        self.prg_bank1 = (bank_bits & 0b01111) % self.prg_banks;
                     }
                     PRGMode::FixedLast => {
-                        self.prg_bank0 = bank_bits & 0b01111;
+                        self.prg_bank0 = (bank_bits & 0b01111) % self.prg_banks;
                         self.prg_bank1 = self.prg_banks - 1;
                     }
                 }
@@ -98,14 +106,22 @@ impl Mapper001 {
             _ => unreachable!(),
         }
     }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x0000..=0x0fff => self.chr_bank0,
+            _ => self.chr_bank1,
+        };
+        (addr & 0x0fff) as usize + bank * 0x1000
+    }
 }
 
 impl super::Mapper for Mapper001 {
-    fn read_prg(&self, prg: &[u8], addr: u16) -> u8 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
         match addr {
-            0x8000..=0xbfff => prg[addr as usize - 0x8000 + self.prg_bank0 * 0x4000],
-            0xc000..=0xffff => prg[addr as usize - 0xc000 + self.prg_bank1 * 0x4000],
-            _ => unreachable!(),
+            0x8000..=0xbfff => Some(addr as usize - 0x8000 + self.prg_bank0 * 0x4000),
+            0xc000..=0xffff => Some(addr as usize - 0xc000 + self.prg_bank1 * 0x4000),
+            _ => None,
         }
     }
 
@@ -130,17 +146,60 @@ impl super::Mapper for Mapper001 {
         }
     }
 
-    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+    fn prg_ram_offset(&self, addr: u16) -> Option<usize> {
+        let bank = match self.ram_banks {
+            1 => 0,
+            2 => (self.chr_bank0 >> 3) & 1,
+            _ => (self.chr_bank0 >> 2) & 3,
+        };
         match addr {
-            0x0000..=0x0fff => chr[(addr & 0x0fff) as usize + self.chr_bank0 * 0x1000],
-            0x1000..=0x1fff => chr[(addr & 0x0fff) as usize + self.chr_bank1 * 0x1000],
-            _ => unreachable!(),
+            0x6000..=0x7fff => Some(bank * 0x2000 + (addr as usize - 0x6000)),
+            _ => None,
         }
     }
 
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[self.chr_offset(addr) % chr.len()]
+    }
+
+    fn write_chr(&mut self, chr: &mut [u8], addr: u16, data: u8) {
+        let i = self.chr_offset(addr) % chr.len();
+        chr[i] = data;
+    }
+
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.prg_bank0);
+        w.usize(self.prg_bank1);
+        w.usize(self.chr_bank0);
+        w.usize(self.chr_bank1);
+
+        w.u8(self.step);
+        w.u8(self.shifter);
+        w.u8(self.prg_mode.to_bits());
+        w.u8(self.chr_mode.to_bits());
+        w.bool(self.enable_ram);
+
+        self.mirroring.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.prg_bank0 = r.usize()? % self.prg_banks;
+        self.prg_bank1 = r.usize()? % self.prg_banks;
+        self.chr_bank0 = r.usize()? & 0x1f;
+        self.chr_bank1 = r.usize()? & 0x1f;
+
+        self.step = r.u8()? % 5;
+        self.shifter = r.u8()? & 0x1f;
+        self.prg_mode = PRGMode::from_bits(r.u8()? & 0b11);
+        self.chr_mode = CHRMode::from_bits(r.u8()? & 0b01);
+        self.enable_ram = r.bool()?;
+
+        self.mirroring.load_state(r)
+    }
 }
 
 impl PRGMode {
@@ -152,6 +211,14 @@ impl PRGMode {
             _ => unreachable!(),
         }
     }
+
+    fn to_bits(&self) -> u8 {
+        match self {
+            PRGMode::Full => 0b00,
+            PRGMode::FixedFirst => 0b10,
+            PRGMode::FixedLast => 0b11,
+        }
+    }
 }
 
 impl CHRMode {
@@ -162,4 +229,11 @@ impl CHRMode {
             _ => unreachable!(),
         }
     }
+
+    fn to_bits(&self) -> u8 {
+        match self {
+            CHRMode::Full => 0b00,
+            CHRMode::Half => 0b01,
+        }
+    }
 }
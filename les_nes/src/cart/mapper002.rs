@@ -1,46 +1,56 @@
 use super::Mirroring;
+use crate::state::{StateReader, StateResult, StateWriter};
 
 /// 002, UxROM
 ///
 /// 0x8000-0xbfff: 16 KB switchable PRG banks,
 /// 0xC000-0xffff: 16 KB PRG bank (fixed to the last bank),
 /// 8 KB CHR
+/// submapper 2 is a board with bus conflicts, 1 and 0 are taken as without.
 pub struct Mapper002 {
     prg_bank0: usize,
     prg_bank1: usize,
+    prg_banks: usize,
+    bus_conflicts: bool,
 
     mirroring: Mirroring,
 }
 
 impl Mapper002 {
-    pub fn new(mirroring: Mirroring, prg_banks: usize) -> Self {
-        assert!(
-            prg_banks <= 256,
-            "invalid banks for Mapper002, prg: {}",
-            prg_banks
-        );
-
-        Self {
+    pub fn new(mirroring: Mirroring, prg_banks: usize, submapper: u8) -> Option<Self> {
+        if !(1..=256).contains(&prg_banks) {
+            return None;
+        }
+
+        Some(Self {
             prg_bank0: 0,
             prg_bank1: prg_banks - 1,
+            prg_banks,
+            bus_conflicts: submapper == 2,
 
             mirroring,
-        }
+        })
     }
 }
 
 impl super::Mapper for Mapper002 {
-    fn read_prg(&self, prg: &[u8], addr: u16) -> u8 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
         match addr {
-            0x8000..=0xbfff => prg[addr as usize - 0x8000 + self.prg_bank0 * 0x4000],
-            0xc000..=0xffff => prg[addr as usize - 0xc000 + self.prg_bank1 * 0x4000],
-            _ => unreachable!(),
+            0x8000..=0xbfff => Some(addr as usize - 0x8000 + self.prg_bank0 * 0x4000),
+            0xc000..=0xffff => Some(addr as usize - 0xc000 + self.prg_bank1 * 0x4000),
+            _ => None,
         }
     }
 
-    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+    fn write_prg(&mut self, prg: &mut [u8], addr: u16, data: u8) {
+        let data = if self.bus_conflicts {
+            data & self.read_prg(prg, addr)
+        } else {
+            data
+        };
+
         match addr {
-            0x8000..=0xffff => self.prg_bank0 = data as usize,
+            0x8000..=0xffff => self.prg_bank0 = data as usize % self.prg_banks,
             _ => unreachable!(),
         }
     }
@@ -52,4 +62,13 @@ impl super::Mapper for Mapper002 {
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.prg_bank0);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.prg_bank0 = r.usize()? % self.prg_banks;
+        Ok(())
+    }
 }
@@ -1,46 +1,59 @@
 use super::Mirroring;
+use crate::state::{StateReader, StateResult, StateWriter};
 
 /// 003, CNROM
 ///
 /// 16 KB or 32KB PRG,
-/// 8 KB switchable CHR banks (up to 256)
+/// 8 KB switchable CHR banks (up to 256),
+/// with bus conflicts on submapper 2.
 pub struct Mapper003 {
     prg_bank1: usize,
     chr_bank: usize,
     chr_banks: usize,
+    bus_conflicts: bool,
 
     mirroring: Mirroring,
 }
 
 impl Mapper003 {
-    pub fn new(mirroring: Mirroring, prg_banks: usize, chr_banks: usize) -> Self {
-        assert!(
-            (prg_banks == 1 || prg_banks == 2) && chr_banks <= 256,
-            "invalid banks for Mapper003, prg: {}, chr: {}",
-            prg_banks,
-            chr_banks
-        );
-
-        Self {
+    pub fn new(
+        mirroring: Mirroring,
+        prg_banks: usize,
+        chr_banks: usize,
+        submapper: u8,
+    ) -> Option<Self> {
+        if !((prg_banks == 1 || prg_banks == 2) && (1..=256).contains(&chr_banks)) {
+            return None;
+        }
+
+        Some(Self {
             prg_bank1: prg_banks - 1,
             chr_bank: 0,
             chr_banks,
+            bus_conflicts: submapper == 2,
 
             mirroring,
-        }
+        })
     }
 }
 
 impl super::Mapper for Mapper003 {
-    fn read_prg(&self, prg: &[u8], addr: u16) -> u8 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
         match addr {
-            0x8000..=0xbfff => prg[addr as usize - 0x8000],
-            0xc000..=0xffff => prg[addr as usize - 0xc000 + self.prg_bank1 * 0x4000],
-            _ => unreachable!(),
+            0x8000..=0xbfff => Some(addr as usize - 0x8000),
+            0xc000..=0xffff => Some(addr as usize - 0xc000 + self.prg_bank1 * 0x4000),
+            _ => None,
         }
     }
 
-    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+    fn write_prg(&mut self, prg: &mut [u8], addr: u16, data: u8) {
+        // the register sees the rom byte at `addr` ANDed in
+        let data = if self.bus_conflicts {
+            data & self.read_prg(prg, addr)
+        } else {
+            data
+        };
+
         match addr {
             0x8000..=0xffff => self.chr_bank = data as usize % self.chr_banks,
             _ => unreachable!(),
@@ -54,4 +67,13 @@ impl super::Mapper for Mapper003 {
     fn mirroring(&self) -> Mirroring {
         self.mirroring
     }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.chr_bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.chr_bank = r.usize()? % self.chr_banks;
+        Ok(())
+    }
 }
@@ -1,4 +1,5 @@
 use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
 use bit_field::BitField;
 
 #[derive(Debug, PartialEq)]
@@ -13,7 +14,30 @@ enum ChrMode {
     High2KB,
 }
 
+/// cpu cycles A12 has to stay low before a rise clocks the irq counter,
+/// which hides the rises between the fetches of one scanline
+const A12_FILTER: usize = 3;
+
+/// boards and chips sharing the MMC3 register layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Board {
+    Mmc3,
+    /// 1 KB of ram inside the chip at $7000-$7FFF, each 512 bytes with their own protect bits
+    Mmc6,
+    /// 118, TxSROM, chr bank bit 7 picks the ciram page of each nametable
+    TxSrom,
+    /// 119, TQROM, chr banks with bit 6 set are in 8 KB of chr ram next to the rom
+    TqRom,
+}
+
+/// 004, MMC3 and MMC6, 118 TxSROM and 119 TQROM
+///
+/// the submapper picks the chip: 1 is MMC6, 4 is MMC3A with the old irq behaviour.
 pub struct Mapper004 {
+    board: Board,
+    /// MMC3A only fires on a counter that was decremented to 0 or reloaded by $C001
+    old_irq: bool,
+
     prg_banks: [usize; 4],
     chr_banks: [usize; 8],
     prg_max: usize,
@@ -25,17 +49,44 @@ pub struct Mapper004 {
 
     irq_latch: u8,
     irq_counter: u8,
+    irq_reload: bool,
+    /// cpu cycles, the time base of the A12 filter
+    cycles: usize,
+    a12: bool,
+    a12_low_since: usize,
     irq_on: bool,
     irq_level: bool,
 
+    /// $A001 bit 7 and 6 on MMC3
+    ram_enabled: bool,
+    ram_protected: bool,
+    /// $8000 bit 5 on MMC6
+    mmc6_ram_enabled: bool,
+    /// $A001 bit 4-7 on MMC6, read and write enables of each half
+    mmc6_ram_access: u8,
+
+    /// a four screen board ignores $A000
+    four_screen: bool,
     mirroring: Mirroring,
 }
 
 impl Mapper004 {
-    pub fn new(mirroring: Mirroring, prg_banks: usize) -> Self {
-        assert!(prg_banks >= 1);
+    pub fn new(mapper: u16, submapper: u8, mirroring: Mirroring, prg_banks: usize) -> Option<Self> {
+        if prg_banks == 0 {
+            return None;
+        }
+
+        let board = match (mapper, submapper) {
+            (118, _) => Board::TxSrom,
+            (119, _) => Board::TqRom,
+            (_, 1) => Board::Mmc6,
+            _ => Board::Mmc3,
+        };
         let prg_max = prg_banks * 2;
-        Self {
+        Some(Self {
+            board,
+            old_irq: board == Board::Mmc3 && submapper == 4,
+
             prg_banks: [0, 1, prg_max - 2, prg_max - 1],
             chr_banks: [0; 8],
             prg_max,
@@ -47,11 +98,21 @@ impl Mapper004 {
 
             irq_latch: 0,
             irq_counter: 0,
+            irq_reload: false,
+            cycles: 0,
+            a12: false,
+            a12_low_since: 0,
             irq_on: false,
             irq_level: false,
 
+            ram_enabled: true,
+            ram_protected: false,
+            mmc6_ram_enabled: false,
+            mmc6_ram_access: 0,
+
+            four_screen: mirroring == Mirroring::FourScreen,
             mirroring,
-        }
+        })
     }
 
     fn bank_select(&mut self, addr: u16, data: u8) {
@@ -59,6 +120,12 @@ impl Mapper004 {
             self.bank_reg = data.get_bits(0..3);
             self.prg_mode = data.get_bit(6).into();
             self.chr_mode = data.get_bit(7).into();
+            if self.board == Board::Mmc6 {
+                self.mmc6_ram_enabled = data.get_bit(5);
+                if !self.mmc6_ram_enabled {
+                    self.mmc6_ram_access = 0;
+                }
+            }
         } else {
             self.bank_regs[self.bank_reg as usize] = data;
         }
@@ -66,6 +133,54 @@ impl Mapper004 {
         self.update_banks();
     }
 
+    fn write_ram_protect(&mut self, data: u8) {
+        match self.board {
+            Board::Mmc6 if self.mmc6_ram_enabled => self.mmc6_ram_access = data & 0xf0,
+            Board::Mmc6 => {}
+            _ => {
+                self.ram_enabled = data.get_bit(7);
+                self.ram_protected = data.get_bit(6);
+            }
+        }
+    }
+
+    /// the MMC6 enable bits for the 512 bytes half `addr` is in, (read, write)
+    fn mmc6_access(&self, addr: u16) -> (bool, bool) {
+        let shift = if addr & 0x200 == 0 { 4 } else { 6 };
+        let bits = self.mmc6_ram_access >> shift;
+        (bits.get_bit(1), bits.get_bit(0))
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        // 8 * 1KB chr banks
+        let index = (addr >> 10) as usize & 0b111;
+        (addr as usize & 0x3ff) + self.chr_banks[index] * 0x400
+    }
+
+    /// the ciram page TxSROM puts at nametable `n`
+    fn txsrom_page(&self, n: usize) -> usize {
+        self.chr_banks[n] >> 7 & 1
+    }
+
+    /// a filtered rise of A12, about once a scanline
+    fn clock_irq(&mut self) {
+        let reloaded = self.irq_reload;
+        let mut decremented = false;
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+            decremented = true;
+        }
+
+        // the newer chips fire on every clock that leaves the counter at 0
+        let fires = !self.old_irq || decremented || reloaded;
+        if self.irq_on && self.irq_counter == 0 && fires {
+            self.irq_level = true;
+        }
+    }
+
     fn update_banks(&mut self) {
         match self.prg_mode {
             PrgMode::SwapLow => {
@@ -107,18 +222,23 @@ impl Mapper004 {
 }
 
 impl super::Mapper for Mapper004 {
-    fn read_prg(&self, prg: &[u8], addr: u16) -> u8 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr < 0x8000 {
+            return None;
+        }
         // 4 * 8KB prg banks
         let index = (addr >> 13) as usize & 0b11;
         let offset = addr as usize & 0x1fff;
-        prg[offset + self.prg_banks[index] * 0x2000]
+        Some(offset + self.prg_banks[index] * 0x2000)
     }
 
     fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
         match addr {
             0x8000..=0x9fff => self.bank_select(addr, data),
             0xa000..=0xbfff => {
-                if addr % 2 == 0 {
+                if !addr.is_multiple_of(2) {
+                    self.write_ram_protect(data);
+                } else if !self.four_screen && self.board != Board::TxSrom {
                     self.mirroring = if !data.get_bit(0) {
                         Mirroring::Vertical
                     } else {
@@ -131,6 +251,7 @@ impl super::Mapper for Mapper004 {
                     self.irq_latch = data;
                 } else {
                     self.irq_counter = 0;
+                    self.irq_reload = true;
                 }
             }
             0xe000..=0xffff => {
@@ -143,23 +264,71 @@ impl super::Mapper for Mapper004 {
         }
     }
 
+    fn prg_ram_offset(&self, addr: u16) -> Option<usize> {
+        match (self.board, addr) {
+            (Board::Mmc6, 0x7000..=0x7fff) => {
+                let (read, write) = self.mmc6_access(addr);
+                (self.mmc6_ram_enabled && (read || write)).then_some(addr as usize & 0x3ff)
+            }
+            (Board::Mmc6, _) => None,
+            (_, 0x6000..=0x7fff) if self.ram_enabled => Some(addr as usize - 0x6000),
+            _ => None,
+        }
+    }
+
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        match self.board {
+            Board::Mmc6 => self.mmc6_access(addr).1,
+            _ => !self.ram_protected,
+        }
+    }
+
+    /// a write only MMC6 half reads as 0
+    fn prg_ram_readable(&self, addr: u16) -> bool {
+        self.board != Board::Mmc6 || self.mmc6_access(addr).0
+    }
+
     fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
-        // 8 * 1KB chr banks
-        let index = (addr >> 10) as usize & 0b111;
-        let offset = addr as usize & 0x3ff;
-        chr[offset + self.chr_banks[index] * 0x400]
+        chr[self.chr_offset(addr) % chr.len()]
     }
 
-    fn update_scanline(&mut self) {
-        if self.irq_counter == 0 {
-            self.irq_counter = self.irq_latch;
-        } else {
-            self.irq_counter -= 1;
+    fn write_chr(&mut self, chr: &mut [u8], addr: u16, data: u8) {
+        let i = self.chr_offset(addr) % chr.len();
+        chr[i] = data;
+    }
 
-            if self.irq_on && self.irq_counter == 0 {
-                self.irq_level = true;
-            }
+    fn chr_ram_offset(&self, addr: u16) -> Option<usize> {
+        let bank = self.chr_banks[(addr >> 10) as usize & 0b111];
+        (self.board == Board::TqRom && bank.get_bit(6))
+            .then_some((bank & 0b111) * 0x400 + (addr as usize & 0x3ff))
+    }
+
+    fn min_chr_ram_size(&self) -> usize {
+        match self.board {
+            Board::TqRom => 0x2000,
+            _ => 0,
+        }
+    }
+
+    fn ciram_offset(&self, n: usize) -> usize {
+        match self.board {
+            Board::TxSrom => self.txsrom_page(n) * 0x400,
+            _ => super::MIRRORING_MAP[self.mirroring() as usize][n],
+        }
+    }
+
+    fn tick(&mut self) {
+        self.cycles += 1;
+    }
+
+    fn ppu_address(&mut self, addr: u16) {
+        let a12 = addr.get_bit(12);
+        if a12 && !self.a12 && self.cycles - self.a12_low_since >= A12_FILTER {
+            self.clock_irq();
+        } else if !a12 && self.a12 {
+            self.a12_low_since = self.cycles;
         }
+        self.a12 = a12;
     }
 
     fn poll_irq(&mut self) -> bool {
@@ -167,7 +336,71 @@ impl super::Mapper for Mapper004 {
     }
 
     fn mirroring(&self) -> Mirroring {
-        self.mirroring
+        if self.board != Board::TxSrom {
+            return self.mirroring;
+        }
+        match [0, 1, 2, 3].map(|n| self.txsrom_page(n)) {
+            [0, 0, 1, 1] => Mirroring::Horizontal,
+            [0, 1, 0, 1] => Mirroring::Vertical,
+            [0, 0, 0, 0] => Mirroring::SingleScreen0,
+            [1, 1, 1, 1] => Mirroring::SingleScreen1,
+            _ => Mirroring::FourScreen,
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        self.prg_banks.iter().for_each(|&b| w.usize(b));
+        self.chr_banks.iter().for_each(|&b| w.usize(b));
+
+        w.u8(self.bank_reg);
+        w.bytes(&self.bank_regs);
+        w.bool(self.prg_mode == PrgMode::SwapHigh);
+        w.bool(self.chr_mode == ChrMode::High2KB);
+
+        w.u8(self.irq_latch);
+        w.u8(self.irq_counter);
+        w.bool(self.irq_reload);
+        w.bool(self.a12);
+        w.usize(self.cycles - self.a12_low_since);
+        w.bool(self.irq_on);
+        w.bool(self.irq_level);
+
+        w.bool(self.ram_enabled);
+        w.bool(self.ram_protected);
+        w.bool(self.mmc6_ram_enabled);
+        w.u8(self.mmc6_ram_access);
+
+        self.mirroring.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        for b in self.prg_banks.iter_mut() {
+            *b = r.usize()? % self.prg_max;
+        }
+        for b in self.chr_banks.iter_mut() {
+            *b = r.usize()? & 0xff;
+        }
+
+        self.bank_reg = r.u8()? & 0b111;
+        r.bytes(&mut self.bank_regs)?;
+        self.prg_mode = r.bool()?.into();
+        self.chr_mode = r.bool()?.into();
+
+        self.irq_latch = r.u8()?;
+        self.irq_counter = r.u8()?;
+        self.irq_reload = r.bool()?;
+        self.a12 = r.bool()?;
+        self.cycles = r.usize()?.min(A12_FILTER);
+        self.a12_low_since = 0;
+        self.irq_on = r.bool()?;
+        self.irq_level = r.bool()?;
+
+        self.ram_enabled = r.bool()?;
+        self.ram_protected = r.bool()?;
+        self.mmc6_ram_enabled = r.bool()?;
+        self.mmc6_ram_access = r.u8()? & 0xf0;
+
+        self.mirroring.load_state(r)
     }
 }
 
@@ -0,0 +1,512 @@
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use audio::Mmc5Audio;
+use bit_field::BitField;
+
+mod audio;
+
+const EXRAM_SIZE: usize = 0x400;
+
+/// ppu fetches per rendered line: 32 tiles * 4, 8 sprites * 4, 2 prefetched tiles * 4
+/// and the 2 dummy nametable fetches
+const LINE_FETCHES: usize = 170;
+const SPRITE_FETCHES: std::ops::Range<usize> = 128..160;
+const PREFETCHES: std::ops::Range<usize> = 160..168;
+
+/// 005, MMC5
+///
+/// 4 prg modes from 32 KB to 8 KB banks, ram can be banked into $6000-$DFFF,
+/// 4 chr modes from 8 KB to 1 KB banks with separate sets for 8x16 sprites and background,
+/// 1 KB ExRAM usable as nametable, extended attributes or plain ram,
+/// a vertical split, a scanline irq, a multiplier and 2 pulses plus pcm of audio.
+pub struct Mapper005 {
+    /// $5113-$5117
+    prg_regs: [u8; 5],
+    prg_mode: u8,
+    /// in 8 KB units
+    prg_max: usize,
+    ram_protect: [u8; 2],
+
+    /// $5120-$5127, with the upper bits of $5130
+    chr_a: [usize; 8],
+    /// $5128-$512B
+    chr_b: [usize; 4],
+    chr_mode: u8,
+    chr_upper: u8,
+    /// whether $5128-$512B were written last
+    chr_b_last: bool,
+    /// snooped from $2000
+    sprites_8x16: bool,
+
+    exram: Box<[u8; EXRAM_SIZE]>,
+    exram_mode: u8,
+    /// $5105, 2 bits per nametable: ciram a, ciram b, exram, fill
+    nt_slots: u8,
+    fill_tile: u8,
+    fill_attr: u8,
+    /// ExRAM byte of the tile being fetched, in extended attribute mode
+    ext: u8,
+
+    /// $5200-$5202
+    split_ctrl: u8,
+    split_scroll: u8,
+    split_bank: u8,
+
+    /// scanline detection from the ppu fetches
+    last_addr: u16,
+    matches: u8,
+    /// fetches since the start of the line
+    fetches: usize,
+    /// cpu cycles without ppu reads
+    idle: u8,
+    in_frame: bool,
+    scanline: u8,
+
+    irq_target: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    multiplicand: u8,
+    multiplier: u8,
+
+    audio: Mmc5Audio,
+}
+
+impl Mapper005 {
+    pub fn new(prg_banks: usize) -> Option<Self> {
+        if prg_banks == 0 {
+            return None;
+        }
+
+        Some(Self {
+            prg_regs: [0, 0, 0, 0, 0xff],
+            prg_mode: 3,
+            prg_max: prg_banks * 2,
+            ram_protect: [0; 2],
+
+            chr_a: [0; 8],
+            chr_b: [0; 4],
+            chr_mode: 0,
+            chr_upper: 0,
+            chr_b_last: false,
+            sprites_8x16: false,
+
+            exram: Box::new([0; EXRAM_SIZE]),
+            exram_mode: 0,
+            nt_slots: 0,
+            fill_tile: 0,
+            fill_attr: 0,
+            ext: 0,
+
+            split_ctrl: 0,
+            split_scroll: 0,
+            split_bank: 0,
+
+            last_addr: 0,
+            matches: 0,
+            fetches: 0,
+            idle: 0,
+            in_frame: false,
+            scanline: 0,
+
+            irq_target: 0,
+            irq_enabled: false,
+            irq_pending: false,
+
+            multiplicand: 0xff,
+            multiplier: 0xff,
+
+            audio: Mmc5Audio::new(),
+        })
+    }
+
+    /// the register and bank size that maps `addr` ($8000-$FFFF),
+    /// bit 7 set means rom and is forced for $5117
+    fn prg_slot(&self, addr: u16) -> (u8, usize) {
+        let [_, r0, r1, r2, r3] = self.prg_regs;
+        match (self.prg_mode, addr) {
+            (0, _) => (r3 | 0x80, 0x8000),
+            (1 | 2, 0x8000..=0xbfff) => (r1, 0x4000),
+            (1, _) => (r3 | 0x80, 0x4000),
+            (2, 0xc000..=0xdfff) | (3, 0xc000..=0xdfff) => (r2, 0x2000),
+            (3, 0x8000..=0x9fff) => (r0, 0x2000),
+            (3, 0xa000..=0xbfff) => (r1, 0x2000),
+            _ => (r3 | 0x80, 0x2000),
+        }
+    }
+
+    fn prg_bank_offset(&self, bank: usize, size: usize, addr: u16) -> usize {
+        let bank = bank & !(size / 0x2000 - 1);
+        bank * 0x2000 + (addr as usize & (size - 1))
+    }
+
+    /// the position of the next fetch in the current line, if rendering
+    fn fetch_pos(&self) -> Option<usize> {
+        self.in_frame.then_some(self.fetches % LINE_FETCHES)
+    }
+
+    /// the tile column and line of the next fetch, if it is a background one
+    fn bg_tile(&self) -> Option<(usize, usize)> {
+        match self.fetch_pos()? {
+            pos if pos < SPRITE_FETCHES.start => Some((pos / 4 + 2, self.scanline as usize)),
+            pos if PREFETCHES.contains(&pos) => {
+                Some(((pos - PREFETCHES.start) / 4, self.scanline as usize + 1))
+            }
+            _ => None,
+        }
+    }
+
+    /// the tile column and the y of the split region, if the next fetch is in it
+    fn split_tile(&self) -> Option<(usize, usize)> {
+        if !self.split_ctrl.get_bit(7) || self.exram_mode >= 2 {
+            return None;
+        }
+        let (tile, line) = self.bg_tile()?;
+        let tile = tile % 32;
+        let count = self.split_ctrl.get_bits(0..5) as usize;
+        let inside = if self.split_ctrl.get_bit(6) {
+            tile >= count
+        } else {
+            tile < count
+        };
+        inside.then_some((tile, (self.split_scroll as usize + line) % 240))
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let addr = addr as usize;
+        if let Some((_, y)) = self.split_tile() {
+            return self.split_bank as usize * 0x1000 + (addr & 0x0ff8) + (y & 7);
+        }
+        if self.exram_mode == 1 && self.bg_tile().is_some() {
+            let bank = (self.ext as usize & 0x3f) | (self.chr_upper as usize) << 6;
+            return bank * 0x1000 + (addr & 0x0fff);
+        }
+
+        let use_b = if self.sprites_8x16 && self.in_frame {
+            self.bg_tile().is_some()
+        } else {
+            self.chr_b_last
+        };
+        let size = 0x2000 >> self.chr_mode;
+        let bank = if use_b {
+            let slot = (addr & 0x0fff) / size.min(0x1000);
+            self.chr_b[(slot + 1) * (4 >> self.chr_mode.saturating_sub(1)) - 1]
+        } else {
+            let slot = addr / size;
+            self.chr_a[(slot + 1) * (8 >> self.chr_mode) - 1]
+        };
+        bank * size + (addr & (size - 1))
+    }
+
+    fn nt_slot(&self, addr: u16) -> u8 {
+        (self.nt_slots >> (((addr as usize >> 10) & 0b11) * 2)) & 0b11
+    }
+
+    fn line_start(&mut self) {
+        if !self.in_frame {
+            self.in_frame = true;
+            self.scanline = 0;
+            self.irq_pending = false;
+        } else {
+            self.scanline = self.scanline.wrapping_add(1);
+            if self.irq_target != 0 && self.scanline == self.irq_target {
+                self.irq_pending = true;
+            }
+        }
+        self.fetches = 0;
+    }
+
+    fn write_reg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000..=0x5015 => self.audio.write(addr, data),
+            0x5100 => self.prg_mode = data & 0b11,
+            0x5101 => self.chr_mode = data & 0b11,
+            0x5102 | 0x5103 => self.ram_protect[addr as usize - 0x5102] = data & 0b11,
+            0x5104 => self.exram_mode = data & 0b11,
+            0x5105 => self.nt_slots = data,
+            0x5106 => self.fill_tile = data,
+            0x5107 => self.fill_attr = data & 0b11,
+            0x5113..=0x5117 => self.prg_regs[addr as usize - 0x5113] = data,
+            0x5120..=0x5127 => {
+                self.chr_a[addr as usize - 0x5120] = data as usize | (self.chr_upper as usize) << 8;
+                self.chr_b_last = false;
+            }
+            0x5128..=0x512b => {
+                self.chr_b[addr as usize - 0x5128] = data as usize | (self.chr_upper as usize) << 8;
+                self.chr_b_last = true;
+            }
+            0x5130 => self.chr_upper = data & 0b11,
+            0x5200 => self.split_ctrl = data,
+            0x5201 => self.split_scroll = data,
+            0x5202 => self.split_bank = data,
+            0x5203 => self.irq_target = data,
+            0x5204 => self.irq_enabled = data.get_bit(7),
+            0x5205 => self.multiplicand = data,
+            0x5206 => self.multiplier = data,
+            0x5c00..=0x5fff => {
+                let i = addr as usize - 0x5c00;
+                match self.exram_mode {
+                    // only writable while rendering, 0 is written otherwise
+                    0 | 1 => self.exram[i] = if self.in_frame { data } else { 0 },
+                    2 => self.exram[i] = data,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl super::Mapper for Mapper005 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let (bank, size) = self.prg_slot(addr);
+        if !bank.get_bit(7) {
+            return None;
+        }
+        let offset = self.prg_bank_offset(bank as usize & 0x7f, size, addr);
+        Some(offset % (self.prg_max * 0x2000))
+    }
+
+    fn peek_low(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x5015 => Some(self.audio.status()),
+            0x5204 => {
+                let mut data = 0;
+                data.set_bit(7, self.irq_pending);
+                data.set_bit(6, self.in_frame);
+                Some(data)
+            }
+            0x5205 => Some((self.multiplicand as u16 * self.multiplier as u16) as u8),
+            0x5206 => Some(((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8),
+            0x5c00..=0x5fff if self.exram_mode >= 2 => Some(self.exram[addr as usize - 0x5c00]),
+            _ => None,
+        }
+    }
+
+    fn read_low(&mut self, addr: u16) -> Option<u8> {
+        let data = self.peek_low(addr);
+        if addr == 0x5204 {
+            self.irq_pending = false;
+        }
+        data
+    }
+
+    fn write_low(&mut self, addr: u16, data: u8) {
+        self.write_reg(addr, data);
+    }
+
+    fn prg_ram_offset(&self, addr: u16) -> Option<usize> {
+        // $6000-$7FFF is always ram, $5113 bit 7 means nothing there
+        let (bank, size) = match addr {
+            0x6000..=0x7fff => (self.prg_regs[0] & 0x7f, 0x2000),
+            0x8000..=0xdfff => self.prg_slot(addr),
+            _ => return None,
+        };
+        if bank.get_bit(7) {
+            return None;
+        }
+        Some(self.prg_bank_offset(bank as usize & 0x07, size, addr))
+    }
+
+    fn prg_ram_writable(&self, _addr: u16) -> bool {
+        self.ram_protect == [0b10, 0b01]
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[self.chr_offset(addr) % chr.len()]
+    }
+
+    fn write_chr(&mut self, chr: &mut [u8], addr: u16, data: u8) {
+        let i = self.chr_offset(addr) % chr.len();
+        chr[i] = data;
+    }
+
+    fn ciram_offset(&self, n: usize) -> usize {
+        match (self.nt_slots >> (n * 2)) & 0b11 {
+            1 => 0x400,
+            _ => 0,
+        }
+    }
+
+    fn read_nametable(&self, _chr: &[u8], addr: u16) -> Option<u8> {
+        let offset = addr as usize & 0x3ff;
+        let pos = self.fetches % LINE_FETCHES;
+
+        if let Some((tile, y)) = self.split_tile() {
+            let row = y / 8;
+            return Some(if pos.is_multiple_of(4) {
+                self.exram[row * 32 + tile]
+            } else {
+                let attr = self.exram[0x3c0 + (row / 4) * 8 + tile / 4];
+                let shift = ((row & 2) << 1) | (tile & 2);
+                ((attr >> shift) & 0b11) * 0x55
+            });
+        }
+        if self.exram_mode == 1 && pos % 4 == 1 && self.bg_tile().is_some() {
+            return Some((self.ext >> 6) * 0x55);
+        }
+
+        match self.nt_slot(addr) {
+            2 if self.exram_mode < 2 => Some(self.exram[offset]),
+            2 => Some(0),
+            3 if offset >= 0x3c0 => Some(self.fill_attr * 0x55),
+            3 => Some(self.fill_tile),
+            _ => None,
+        }
+    }
+
+    fn write_nametable(&mut self, addr: u16, data: u8) -> bool {
+        match self.nt_slot(addr) {
+            2 => {
+                if self.exram_mode < 2 {
+                    self.exram[addr as usize & 0x3ff] = data;
+                }
+                true
+            }
+            3 => true,
+            _ => false,
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) {
+        self.idle = 0;
+
+        let nametable = (0x2000..0x3000).contains(&addr);
+        self.matches = if nametable && addr == self.last_addr {
+            self.matches + 1
+        } else {
+            0
+        };
+        self.last_addr = addr;
+        if self.matches == 2 {
+            self.line_start();
+        }
+
+        if nametable && self.fetches.is_multiple_of(4) && self.bg_tile().is_some() {
+            self.ext = self.exram[addr as usize & 0x3ff];
+        }
+        self.fetches += 1;
+    }
+
+    fn write_ppu_reg(&mut self, addr: u16, data: u8) {
+        if addr == 0x2000 {
+            self.sprites_8x16 = data.get_bit(5);
+        }
+    }
+
+    fn tick(&mut self) {
+        self.idle = self.idle.saturating_add(1);
+        if self.idle >= 3 {
+            self.in_frame = false;
+            self.matches = 0;
+        }
+        self.audio.tick();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn audio_channels(&self) -> &'static [&'static str] {
+        &audio::CHANNELS
+    }
+
+    fn set_audio_channels(&mut self, enabled: &[bool]) {
+        self.audio.set_channels(enabled);
+    }
+
+    fn poll_irq(&mut self) -> bool {
+        self.irq_enabled && self.irq_pending
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.nt_slots {
+            0x50 => Mirroring::Horizontal,
+            0x44 => Mirroring::Vertical,
+            0x00 => Mirroring::SingleScreen0,
+            0x55 => Mirroring::SingleScreen1,
+            _ => Mirroring::FourScreen,
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.prg_regs);
+        w.u8(self.prg_mode);
+        w.bytes(&self.ram_protect);
+
+        self.chr_a.iter().for_each(|&b| w.usize(b));
+        self.chr_b.iter().for_each(|&b| w.usize(b));
+        w.u8(self.chr_mode);
+        w.u8(self.chr_upper);
+        w.bool(self.chr_b_last);
+        w.bool(self.sprites_8x16);
+
+        w.bytes(self.exram.as_ref());
+        w.u8(self.exram_mode);
+        w.u8(self.nt_slots);
+        w.u8(self.fill_tile);
+        w.u8(self.fill_attr);
+        w.u8(self.ext);
+
+        w.u8(self.split_ctrl);
+        w.u8(self.split_scroll);
+        w.u8(self.split_bank);
+
+        w.u16(self.last_addr);
+        w.u8(self.matches);
+        w.usize(self.fetches);
+        w.u8(self.idle);
+        w.bool(self.in_frame);
+        w.u8(self.scanline);
+
+        w.u8(self.irq_target);
+        w.bool(self.irq_enabled);
+        w.bool(self.irq_pending);
+
+        w.u8(self.multiplicand);
+        w.u8(self.multiplier);
+
+        self.audio.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        r.bytes(&mut self.prg_regs)?;
+        self.prg_mode = r.u8()? & 0b11;
+        r.bytes(&mut self.ram_protect)?;
+
+        for b in self.chr_a.iter_mut().chain(self.chr_b.iter_mut()) {
+            *b = r.usize()? & 0x3ff;
+        }
+        self.chr_mode = r.u8()? & 0b11;
+        self.chr_upper = r.u8()? & 0b11;
+        self.chr_b_last = r.bool()?;
+        self.sprites_8x16 = r.bool()?;
+
+        r.bytes(self.exram.as_mut())?;
+        self.exram_mode = r.u8()? & 0b11;
+        self.nt_slots = r.u8()?;
+        self.fill_tile = r.u8()?;
+        self.fill_attr = r.u8()? & 0b11;
+        self.ext = r.u8()?;
+
+        self.split_ctrl = r.u8()?;
+        self.split_scroll = r.u8()?;
+        self.split_bank = r.u8()?;
+
+        self.last_addr = r.u16()?;
+        self.matches = r.u8()?;
+        self.fetches = r.usize()?;
+        self.idle = r.u8()?;
+        self.in_frame = r.bool()?;
+        self.scanline = r.u8()?;
+
+        self.irq_target = r.u8()?;
+        self.irq_enabled = r.bool()?;
+        self.irq_pending = r.bool()?;
+
+        self.multiplicand = r.u8()?;
+        self.multiplier = r.u8()?;
+
+        self.audio.load_state(r)
+    }
+}
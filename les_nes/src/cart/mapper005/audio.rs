@@ -0,0 +1,111 @@
+use crate::apu::{mix, Channel, Pulse};
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+/// cpu cycles between length and envelope clocks, ~240 Hz
+const FRAME_PERIOD: usize = 7457;
+
+pub const CHANNELS: [&str; 3] = ["MMC5 Pulse1", "MMC5 Pulse2", "MMC5 PCM"];
+
+/// two pulses like the 2A03 ones without sweep, plus 8 bit pcm written at $5011
+pub struct Mmc5Audio {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    pcm: u8,
+    /// pcm read mode takes samples from cpu reads, which isn't supported
+    pcm_read_mode: bool,
+
+    cycles: usize,
+    channels: [bool; 3],
+    output: f32,
+}
+
+impl Mmc5Audio {
+    pub fn new() -> Self {
+        Self {
+            pulse1: Pulse::without_sweep(),
+            pulse2: Pulse::without_sweep(),
+            pcm: 0,
+            pcm_read_mode: false,
+
+            cycles: 0,
+            channels: [true; 3],
+            output: 0.0,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+        if self.cycles.is_multiple_of(2) {
+            self.pulse1.tick();
+            self.pulse2.tick();
+        }
+        if self.cycles.is_multiple_of(FRAME_PERIOD) {
+            for p in [&mut self.pulse1, &mut self.pulse2] {
+                p.tick_len();
+                p.tick_eve();
+            }
+        }
+
+        let [p1, p2, pcm] = self.channels.map(|on| on as u8);
+        let pulse = self.pulse1.sample() * p1 + self.pulse2.sample() * p2;
+        // about as loud as the dmc at the same level
+        self.output = mix(pulse, (self.pcm >> 1) * pcm);
+    }
+
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    pub fn set_channels(&mut self, enabled: &[bool]) {
+        for (c, &e) in self.channels.iter_mut().zip(enabled) {
+            *c = e;
+        }
+    }
+
+    /// $5015
+    pub fn status(&self) -> u8 {
+        let mut data = 0;
+        data.set_bit(1, self.pulse2.enabled());
+        data.set_bit(0, self.pulse1.enabled());
+        data
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000 => self.pulse1.write_reg0(data),
+            0x5002 => self.pulse1.write_reg2(data),
+            0x5003 => self.pulse1.write_reg3(data),
+            0x5004 => self.pulse2.write_reg0(data),
+            0x5006 => self.pulse2.write_reg2(data),
+            0x5007 => self.pulse2.write_reg3(data),
+            0x5010 => self.pcm_read_mode = data.get_bit(0),
+            // 0 can't be written, it would trigger the pcm irq instead
+            0x5011 if !self.pcm_read_mode && data != 0 => self.pcm = data,
+            0x5015 => {
+                self.pulse1.set_enable(data.get_bit(0));
+                self.pulse2.set_enable(data.get_bit(1));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Snapshot for Mmc5Audio {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.pulse1.save_state(w);
+        self.pulse2.save_state(w);
+        w.u8(self.pcm);
+        w.bool(self.pcm_read_mode);
+        w.usize(self.cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.pulse1.load_state(r)?;
+        self.pulse2.load_state(r)?;
+        self.pcm = r.u8()?;
+        self.pcm_read_mode = r.bool()?;
+        self.cycles = r.usize()?;
+        Ok(())
+    }
+}
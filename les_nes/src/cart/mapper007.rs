@@ -0,0 +1,72 @@
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+
+/// 007, AxROM
+///
+/// 0x8000-0xffff: 32 KB switchable PRG banks,
+/// 8 KB CHR RAM,
+/// one register at 0x8000-0xffff: `...M PPPP`, M selects the single screen nametable.
+/// ANROM and AMROM (submapper 2) have bus conflicts, AOROM (submapper 1) doesn't.
+pub struct Mapper007 {
+    prg_bank: usize,
+    prg_banks: usize,
+    bus_conflicts: bool,
+
+    mirroring: Mirroring,
+}
+
+impl Mapper007 {
+    pub fn new(prg_banks: usize, submapper: u8) -> Option<Self> {
+        if !(prg_banks >= 2 && prg_banks.is_multiple_of(2)) {
+            return None;
+        }
+
+        Some(Self {
+            prg_bank: 0,
+            prg_banks: prg_banks / 2,
+            bus_conflicts: submapper == 2,
+
+            mirroring: Mirroring::SingleScreen0,
+        })
+    }
+}
+
+impl super::Mapper for Mapper007 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        Some(self.prg_bank * 0x8000 + (addr as usize & 0x7fff))
+    }
+
+    fn write_prg(&mut self, prg: &mut [u8], addr: u16, data: u8) {
+        // the rom drives the bus too, only bits that are 1 in both survive
+        let data = if self.bus_conflicts {
+            data & self.read_prg(prg, addr)
+        } else {
+            data
+        };
+
+        self.prg_bank = (data as usize & 0b1111) % self.prg_banks;
+        self.mirroring = if data & 0b1_0000 == 0 {
+            Mirroring::SingleScreen0
+        } else {
+            Mirroring::SingleScreen1
+        };
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[addr as usize]
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.prg_bank);
+        self.mirroring.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.prg_bank = r.usize()? % self.prg_banks;
+        self.mirroring.load_state(r)
+    }
+}
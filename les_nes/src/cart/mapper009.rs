@@ -0,0 +1,161 @@
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+
+/// the chr half of MMC2 and MMC4
+///
+/// each 4 KB half of the pattern tables has two banks, a latch picks one of them.
+/// fetching tile $FD or $FE flips the latch of its half to the matching bank.
+pub(super) struct ChrLatches {
+    /// `[half][latch]`, latch 0 is $FD and 1 is $FE
+    banks: [[usize; 2]; 2],
+    latches: [usize; 2],
+    /// in 4 KB units
+    chr_max: usize,
+    /// MMC4 reacts to all of $0FD8-$0FDF, MMC2 only to $0FD8 in the left half
+    wide_left: bool,
+}
+
+impl ChrLatches {
+    pub fn new(chr_banks: usize, wide_left: bool) -> Self {
+        Self {
+            banks: [[0; 2]; 2],
+            latches: [1, 1],
+            chr_max: chr_banks * 2,
+            wide_left,
+        }
+    }
+
+    /// $B000-$EFFF
+    pub fn set_bank(&mut self, addr: u16, data: u8) {
+        let n = (addr as usize >> 12) - 0xb;
+        self.banks[n / 2][n % 2] = (data as usize & 0b1_1111) % self.chr_max;
+    }
+
+    pub fn offset(&self, addr: u16) -> usize {
+        let half = addr as usize >> 12;
+        self.banks[half][self.latches[half]] * 0x1000 + (addr as usize & 0x0fff)
+    }
+
+    pub fn ppu_read(&mut self, addr: u16) {
+        if addr >= 0x2000 {
+            return;
+        }
+
+        let half = addr as usize >> 12;
+        let row = if half == 0 && !self.wide_left {
+            addr & 0x0fff
+        } else {
+            addr & 0x0ff8
+        };
+        match row {
+            0x0fd8 => self.latches[half] = 0,
+            0x0fe8 => self.latches[half] = 1,
+            _ => {}
+        }
+    }
+}
+
+impl Snapshot for ChrLatches {
+    fn save_state(&self, w: &mut StateWriter) {
+        for b in self.banks.iter().flatten() {
+            w.usize(*b);
+        }
+        for &l in &self.latches {
+            w.usize(l);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        for b in self.banks.iter_mut().flatten() {
+            *b = r.usize()? % self.chr_max;
+        }
+        for l in self.latches.iter_mut() {
+            *l = r.usize()? & 1;
+        }
+        Ok(())
+    }
+}
+
+/// $F000, `.......M`
+pub(super) fn mirroring(data: u8) -> Mirroring {
+    if data & 1 == 0 {
+        Mirroring::Vertical
+    } else {
+        Mirroring::Horizontal
+    }
+}
+
+/// 009, MMC2
+///
+/// 0x8000-0x9fff: 8 KB switchable PRG bank,
+/// 0xa000-0xffff: the last three 8 KB PRG banks,
+/// 2 * 4 KB CHR banks, each picked from two by a latch, see `ChrLatches`
+pub struct Mapper009 {
+    prg_bank: usize,
+    /// in 8 KB units
+    prg_max: usize,
+    chr: ChrLatches,
+
+    mirroring: Mirroring,
+}
+
+impl Mapper009 {
+    pub fn new(mirroring: Mirroring, prg_banks: usize, chr_banks: usize) -> Option<Self> {
+        if !(prg_banks >= 2 && chr_banks >= 1) {
+            return None;
+        }
+
+        Some(Self {
+            prg_bank: 0,
+            prg_max: prg_banks * 2,
+            chr: ChrLatches::new(chr_banks, false),
+
+            mirroring,
+        })
+    }
+}
+
+impl super::Mapper for Mapper009 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let bank = match addr {
+            0x8000..=0x9fff => self.prg_bank,
+            // the fixed banks
+            0xa000..=0xffff => self.prg_max - 4 + ((addr as usize - 0x8000) >> 13),
+            _ => return None,
+        };
+        Some(bank * 0x2000 + (addr as usize & 0x1fff))
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+        match addr {
+            0xa000..=0xafff => self.prg_bank = (data as usize & 0b1111) % self.prg_max,
+            0xb000..=0xefff => self.chr.set_bank(addr, data),
+            0xf000..=0xffff => self.mirroring = mirroring(data),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[self.chr.offset(addr)]
+    }
+
+    fn ppu_read(&mut self, addr: u16) {
+        self.chr.ppu_read(addr);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.prg_bank);
+        self.chr.save_state(w);
+        self.mirroring.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.prg_bank = r.usize()? % self.prg_max;
+        self.chr.load_state(r)?;
+        self.mirroring.load_state(r)
+    }
+}
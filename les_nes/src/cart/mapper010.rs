@@ -0,0 +1,77 @@
+use super::mapper009::{mirroring, ChrLatches};
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+
+/// 010, MMC4
+///
+/// 0x8000-0xbfff: 16 KB switchable PRG bank,
+/// 0xc000-0xffff: 16 KB PRG bank (fixed to the last bank),
+/// CHR works like MMC2, see `ChrLatches`
+pub struct Mapper010 {
+    prg_bank0: usize,
+    prg_bank1: usize,
+    prg_banks: usize,
+    chr: ChrLatches,
+
+    mirroring: Mirroring,
+}
+
+impl Mapper010 {
+    pub fn new(mirroring: Mirroring, prg_banks: usize, chr_banks: usize) -> Option<Self> {
+        if !(prg_banks >= 1 && chr_banks >= 1) {
+            return None;
+        }
+
+        Some(Self {
+            prg_bank0: 0,
+            prg_bank1: prg_banks - 1,
+            prg_banks,
+            chr: ChrLatches::new(chr_banks, true),
+
+            mirroring,
+        })
+    }
+}
+
+impl super::Mapper for Mapper010 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xbfff => Some(addr as usize - 0x8000 + self.prg_bank0 * 0x4000),
+            0xc000..=0xffff => Some(addr as usize - 0xc000 + self.prg_bank1 * 0x4000),
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+        match addr {
+            0xa000..=0xafff => self.prg_bank0 = (data as usize & 0b1111) % self.prg_banks,
+            0xb000..=0xefff => self.chr.set_bank(addr, data),
+            0xf000..=0xffff => self.mirroring = mirroring(data),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[self.chr.offset(addr)]
+    }
+
+    fn ppu_read(&mut self, addr: u16) {
+        self.chr.ppu_read(addr);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.prg_bank0);
+        self.chr.save_state(w);
+        self.mirroring.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.prg_bank0 = r.usize()? % self.prg_banks;
+        self.chr.load_state(r)?;
+        self.mirroring.load_state(r)
+    }
+}
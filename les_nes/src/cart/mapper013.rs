@@ -0,0 +1,74 @@
+use super::Mirroring;
+use crate::state::{StateReader, StateResult, StateWriter};
+
+/// 013, CPROM
+///
+/// 0x8000-0xffff: 32 KB PRG,
+/// 0x0000-0x0fff: the first 4 KB of 16 KB CHR RAM,
+/// 0x1000-0x1fff: 4 KB switchable CHR RAM bank
+pub struct Mapper013 {
+    chr_bank: usize,
+
+    mirroring: Mirroring,
+}
+
+impl Mapper013 {
+    pub fn new(mirroring: Mirroring, prg_banks: usize, chr_banks: usize) -> Option<Self> {
+        if !(prg_banks == 2 && chr_banks == 0) {
+            return None;
+        }
+
+        Some(Self {
+            chr_bank: 0,
+
+            mirroring,
+        })
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        match addr {
+            0x0000..=0x0fff => addr as usize,
+            _ => self.chr_bank * 0x1000 + (addr as usize & 0x0fff),
+        }
+    }
+}
+
+impl super::Mapper for Mapper013 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        Some(addr as usize & 0x7fff)
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], _addr: u16, data: u8) {
+        self.chr_bank = data as usize & 0b11;
+    }
+
+    fn prg_ram_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[self.chr_offset(addr) % chr.len()]
+    }
+
+    fn write_chr(&mut self, chr: &mut [u8], addr: u16, data: u8) {
+        let i = self.chr_offset(addr) % chr.len();
+        chr[i] = data;
+    }
+
+    fn min_chr_ram_size(&self) -> usize {
+        0x4000
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.chr_bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.chr_bank = r.usize()? & 0b11;
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use audio::N163Audio;
+use bit_field::BitField;
+
+mod audio;
+
+/// banks from $E0 up select a ciram page instead of chr rom
+const CIRAM_BANKS: u8 = 0xe0;
+
+/// 019, Namco 163
+///
+/// 0x8000-0xdfff: 3 * 8 KB switchable PRG banks,
+/// 0xe000-0xffff: the last 8 KB PRG bank,
+/// 8 * 1 KB CHR banks and 4 * 1 KB nametable banks, each either chr rom or a ciram page.
+pub struct Mapper019 {
+    prg_banks: [usize; 3],
+    /// in 8 KB units
+    prg_max: usize,
+    chr_banks: [u8; 8],
+    nt_banks: [u8; 4],
+    /// $E800 bit 6 and 7 keep ciram out of $0000-$0FFF and $1000-$1FFF
+    ciram_disabled: [bool; 2],
+    /// $F800, ram writes need $4x, bit 0-3 protect each 2 KB
+    ram_protect: u8,
+
+    /// 15 bits, counts up to $7FFF
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    audio: N163Audio,
+}
+
+impl Mapper019 {
+    pub fn new(prg_banks: usize, chr_banks: usize) -> Option<Self> {
+        if prg_banks == 0 || chr_banks == 0 {
+            return None;
+        }
+
+        Some(Self {
+            prg_banks: [0; 3],
+            prg_max: prg_banks * 2,
+            chr_banks: [0; 8],
+            nt_banks: [CIRAM_BANKS; 4],
+            ciram_disabled: [false; 2],
+            ram_protect: 0,
+
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+
+            audio: N163Audio::new(),
+        })
+    }
+
+    fn chr_offset(bank: u8, addr: u16) -> usize {
+        bank as usize * 0x400 + (addr as usize & 0x3ff)
+    }
+
+    fn nt_bank(&self, addr: u16) -> u8 {
+        self.nt_banks[(addr as usize >> 10) & 3]
+    }
+}
+
+impl super::Mapper for Mapper019 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let bank = match addr {
+            0x8000..=0xdfff => self.prg_banks[(addr as usize - 0x8000) >> 13],
+            0xe000..=0xffff => self.prg_max - 1,
+            _ => return None,
+        };
+        Some(bank * 0x2000 + (addr as usize & 0x1fff))
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+        match addr {
+            0x8000..=0xbfff => self.chr_banks[(addr as usize - 0x8000) >> 11] = data,
+            0xc000..=0xdfff => self.nt_banks[(addr as usize - 0xc000) >> 11] = data,
+            0xe000..=0xe7ff => {
+                self.prg_banks[0] = (data as usize & 0x3f) % self.prg_max;
+                self.audio.set_disabled(data.get_bit(6));
+            }
+            0xe800..=0xefff => {
+                self.prg_banks[1] = (data as usize & 0x3f) % self.prg_max;
+                self.ciram_disabled = [data.get_bit(6), data.get_bit(7)];
+            }
+            0xf000..=0xf7ff => self.prg_banks[2] = (data as usize & 0x3f) % self.prg_max,
+            _ => {
+                self.ram_protect = data;
+                self.audio.write_address(data);
+            }
+        }
+    }
+
+    fn peek_low(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800..=0x4fff => Some(self.audio.peek_data()),
+            0x5000..=0x57ff => Some(self.irq_counter as u8),
+            0x5800..=0x5fff => Some((self.irq_counter >> 8) as u8 | (self.irq_enabled as u8) << 7),
+            _ => None,
+        }
+    }
+
+    fn read_low(&mut self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4800..=0x4fff => Some(self.audio.read_data()),
+            _ => self.peek_low(addr),
+        }
+    }
+
+    fn write_low(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4800..=0x4fff => self.audio.write_data(data),
+            0x5000..=0x57ff => {
+                self.irq_counter.set_bits(0..8, data as u16);
+                self.irq_pending = false;
+            }
+            0x5800..=0x5fff => {
+                self.irq_counter.set_bits(8..15, data as u16 & 0x7f);
+                self.irq_enabled = data.get_bit(7);
+                self.irq_pending = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        let window = (addr as usize - 0x6000) >> 11;
+        self.ram_protect & 0xf0 == 0x40 && !self.ram_protect.get_bit(window)
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize >> 10];
+        chr[Self::chr_offset(bank, addr) % chr.len()]
+    }
+
+    fn chr_ciram_offset(&self, addr: u16) -> Option<usize> {
+        let bank = self.chr_banks[addr as usize >> 10];
+        if bank >= CIRAM_BANKS && !self.ciram_disabled[addr as usize >> 12] {
+            Some(Self::chr_offset(bank & 1, addr))
+        } else {
+            None
+        }
+    }
+
+    fn ciram_offset(&self, n: usize) -> usize {
+        (self.nt_banks[n] as usize & 1) * 0x400
+    }
+
+    fn read_nametable(&self, chr: &[u8], addr: u16) -> Option<u8> {
+        let bank = self.nt_bank(addr);
+        if bank >= CIRAM_BANKS {
+            return None;
+        }
+        Some(chr[Self::chr_offset(bank, addr) % chr.len()])
+    }
+
+    /// writes to a chr rom nametable go nowhere
+    fn write_nametable(&mut self, addr: u16, _data: u8) -> bool {
+        self.nt_bank(addr) < CIRAM_BANKS
+    }
+
+    fn tick(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7fff {
+            self.irq_counter += 1;
+            if self.irq_counter == 0x7fff {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.tick();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn audio_channels(&self) -> &'static [&'static str] {
+        &audio::CHANNELS
+    }
+
+    fn set_audio_channels(&mut self, enabled: &[bool]) {
+        self.audio.set_channels(enabled);
+    }
+
+    fn poll_irq(&mut self) -> bool {
+        self.irq_pending
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        let pages = self.nt_banks.map(|b| (b >= CIRAM_BANKS).then_some(b & 1));
+        match pages {
+            [Some(0), Some(0), Some(1), Some(1)] => Mirroring::Horizontal,
+            [Some(0), Some(1), Some(0), Some(1)] => Mirroring::Vertical,
+            [Some(0), Some(0), Some(0), Some(0)] => Mirroring::SingleScreen0,
+            [Some(1), Some(1), Some(1), Some(1)] => Mirroring::SingleScreen1,
+            _ => Mirroring::FourScreen,
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        self.prg_banks.iter().for_each(|&b| w.usize(b));
+        w.bytes(&self.chr_banks);
+        w.bytes(&self.nt_banks);
+        self.ciram_disabled.iter().for_each(|&d| w.bool(d));
+        w.u8(self.ram_protect);
+
+        w.u16(self.irq_counter);
+        w.bool(self.irq_enabled);
+        w.bool(self.irq_pending);
+
+        self.audio.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        for b in self.prg_banks.iter_mut() {
+            *b = r.usize()? % self.prg_max;
+        }
+        r.bytes(&mut self.chr_banks)?;
+        r.bytes(&mut self.nt_banks)?;
+        for d in self.ciram_disabled.iter_mut() {
+            *d = r.bool()?;
+        }
+        self.ram_protect = r.u8()?;
+
+        self.irq_counter = r.u16()? & 0x7fff;
+        self.irq_enabled = r.bool()?;
+        self.irq_pending = r.bool()?;
+
+        self.audio.load_state(r)
+    }
+}
@@ -0,0 +1,166 @@
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+pub const CHANNELS: [&str; 8] = [
+    "N163 Wave1",
+    "N163 Wave2",
+    "N163 Wave3",
+    "N163 Wave4",
+    "N163 Wave5",
+    "N163 Wave6",
+    "N163 Wave7",
+    "N163 Wave8",
+];
+
+/// output per step of the -120..105 sample, a full volume channel is about as loud as a 2A03 pulse
+const VOLUME: f32 = 0.15 / 120.0;
+
+/// cpu cycles spent on each channel
+const CHANNEL_CYCLES: usize = 15;
+
+const RAM_SIZE: usize = 0x80;
+
+/// the wavetable synth of Namco 163
+///
+/// waves and channel registers share 128 bytes of ram. channel 1 has its
+/// registers at $78-$7F, channel 2 at $70-$77 and so on. only one channel is
+/// updated and heard at a time, so more channels means a lower rate for each.
+pub struct N163Audio {
+    ram: [u8; RAM_SIZE],
+    /// $F800
+    address: u8,
+    auto_increment: bool,
+    /// $E000 bit 6
+    disabled: bool,
+
+    cycles: usize,
+    /// the channel being played, 0-7
+    current: usize,
+    channels: [bool; 8],
+    output: f32,
+}
+
+impl N163Audio {
+    pub fn new() -> Self {
+        Self {
+            ram: [0; RAM_SIZE],
+            address: 0,
+            auto_increment: false,
+            disabled: false,
+
+            cycles: 0,
+            current: 0,
+            channels: [true; 8],
+            output: 0.0,
+        }
+    }
+
+    /// $F800
+    pub fn write_address(&mut self, data: u8) {
+        self.address = data & 0x7f;
+        self.auto_increment = data.get_bit(7);
+    }
+
+    pub fn set_disabled(&mut self, disabled: bool) {
+        self.disabled = disabled;
+        if disabled {
+            self.output = 0.0;
+        }
+    }
+
+    /// $4800, without moving the address
+    pub fn peek_data(&self) -> u8 {
+        self.ram[self.address as usize]
+    }
+
+    /// $4800
+    pub fn read_data(&mut self) -> u8 {
+        let data = self.peek_data();
+        self.step_address();
+        data
+    }
+
+    /// $4800
+    pub fn write_data(&mut self, data: u8) {
+        self.ram[self.address as usize] = data;
+        self.step_address();
+    }
+
+    fn step_address(&mut self) {
+        if self.auto_increment {
+            self.address = (self.address + 1) & 0x7f;
+        }
+    }
+
+    /// the number of channels playing, 1-8
+    fn active_channels(&self) -> usize {
+        self.ram[0x7f].get_bits(4..7) as usize + 1
+    }
+
+    /// advances channel `n` and returns its signed sample
+    fn clock_channel(&mut self, n: usize) -> i32 {
+        let base = 0x78 - n * 8;
+        let regs = &mut self.ram[base..base + 8];
+
+        let freq = regs[0] as u32 | (regs[2] as u32) << 8 | (regs[4] as u32 & 0b11) << 16;
+        let length = 0x100 - (regs[4] as u32 & 0xfc);
+        let mut phase = regs[1] as u32 | (regs[3] as u32) << 8 | (regs[5] as u32) << 16;
+        phase = (phase + freq) % (length << 16);
+        regs[1] = phase as u8;
+        regs[3] = (phase >> 8) as u8;
+        regs[5] = (phase >> 16) as u8;
+
+        let index = ((phase >> 16) as usize + regs[6] as usize) & 0xff;
+        let volume = (regs[7] & 0x0f) as i32;
+        let sample = (self.ram[index >> 1] >> ((index & 1) * 4)) & 0x0f;
+        (sample as i32 - 8) * volume
+    }
+
+    pub fn tick(&mut self) {
+        if self.disabled {
+            return;
+        }
+
+        self.cycles += 1;
+        if self.cycles < CHANNEL_CYCLES {
+            return;
+        }
+        self.cycles = 0;
+
+        let n = self.current;
+        let sample = self.clock_channel(n) * self.channels[n] as i32;
+        self.output = sample as f32 * VOLUME;
+        self.current = (n + 1) % self.active_channels();
+    }
+
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    pub fn set_channels(&mut self, enabled: &[bool]) {
+        for (c, &e) in self.channels.iter_mut().zip(enabled) {
+            *c = e;
+        }
+    }
+}
+
+impl Snapshot for N163Audio {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.u8(self.address);
+        w.bool(self.auto_increment);
+        w.bool(self.disabled);
+        w.usize(self.cycles);
+        w.usize(self.current);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        r.bytes(&mut self.ram)?;
+        self.address = r.u8()? & 0x7f;
+        self.auto_increment = r.bool()?;
+        self.disabled = r.bool()?;
+        self.cycles = r.usize()? % CHANNEL_CYCLES;
+        self.current = r.usize()? % self.active_channels();
+        Ok(())
+    }
+}
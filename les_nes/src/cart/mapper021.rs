@@ -0,0 +1,296 @@
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+/// the irq counter of VRC4, VRC6 and VRC7
+///
+/// counts up from the latch to $FF, either every cpu cycle or every scanline,
+/// which is approximated by a prescaler of 341 ppu dots.
+pub(super) struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pending: bool,
+}
+
+impl VrcIrq {
+    pub fn new() -> Self {
+        Self {
+            latch: 0,
+            counter: 0,
+            prescaler: 341,
+            enabled: false,
+            enable_after_ack: false,
+            cycle_mode: false,
+            pending: false,
+        }
+    }
+
+    pub fn write_latch(&mut self, data: u8) {
+        self.latch = data;
+    }
+
+    /// VRC4 writes the latch a nibble at a time
+    pub fn write_latch_nibble(&mut self, high: bool, data: u8) {
+        if high {
+            self.latch.set_bits(4..8, data & 0x0f);
+        } else {
+            self.latch.set_bits(0..4, data & 0x0f);
+        }
+    }
+
+    pub fn write_control(&mut self, data: u8) {
+        self.enable_after_ack = data.get_bit(0);
+        self.enabled = data.get_bit(1);
+        self.cycle_mode = data.get_bit(2);
+        self.pending = false;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = 341;
+        }
+    }
+
+    pub fn ack(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    /// once per cpu cycle
+    pub fn tick(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.cycle_mode {
+            self.clock();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += 341;
+                self.clock();
+            }
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.counter == 0xff {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+}
+
+impl Snapshot for VrcIrq {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.latch);
+        w.u8(self.counter);
+        w.u16(self.prescaler as u16);
+        w.bool(self.enabled);
+        w.bool(self.enable_after_ack);
+        w.bool(self.cycle_mode);
+        w.bool(self.pending);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.latch = r.u8()?;
+        self.counter = r.u8()?;
+        self.prescaler = (r.u16()? as i16).clamp(-2, 341);
+        self.enabled = r.bool()?;
+        self.enable_after_ack = r.bool()?;
+        self.cycle_mode = r.bool()?;
+        self.pending = r.bool()?;
+        Ok(())
+    }
+}
+
+/// the cpu address lines a board wires to the register select pins
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Lines {
+    a0: u16,
+    a1: u16,
+}
+
+/// 021, 022, 023 and 025, Konami VRC2 and VRC4
+///
+/// the boards only differ in which address lines pick the register and
+/// in VRC2 lacking the irq, the prg swap mode and one chr bank bit.
+/// the NES 2.0 submapper picks the board, without one both VRC4 wirings of the number
+/// are decoded. there is no rom database, so iNES VRC2b and VRC2c dumps run as VRC4.
+///
+/// 0x8000-0x9fff or 0xc000-0xdfff: 8 KB switchable PRG bank,
+/// 0xa000-0xbfff: 8 KB switchable PRG bank,
+/// the other two are the second last and last bank, 8 * 1 KB CHR banks
+pub struct Mapper021 {
+    lines: Lines,
+    vrc2: bool,
+    /// VRC2a ignores the low bit of the chr banks
+    chr_shift: u8,
+
+    prg_banks: [usize; 2],
+    /// in 8 KB units
+    prg_max: usize,
+    prg_swap: bool,
+    ram_enabled: bool,
+    chr_banks: [usize; 8],
+
+    irq: VrcIrq,
+
+    mirroring: Mirroring,
+}
+
+impl Mapper021 {
+    pub fn new(mapper: u16, submapper: u8, mirroring: Mirroring, prg_banks: usize) -> Option<Self> {
+        if prg_banks == 0 {
+            return None;
+        }
+
+        let lines = |a0, a1| Lines { a0, a1 };
+        let (lines, vrc2) = match (mapper, submapper) {
+            // VRC4a, VRC4c
+            (21, 1) => (lines(0x02, 0x04), false),
+            (21, 2) => (lines(0x40, 0x80), false),
+            (21, _) => (lines(0x42, 0x84), false),
+            // VRC2a
+            (22, _) => (lines(0x02, 0x01), true),
+            // VRC4f, VRC4e, VRC2b
+            (23, 1) => (lines(0x01, 0x02), false),
+            (23, 2) => (lines(0x04, 0x08), false),
+            (23, 3) => (lines(0x01, 0x02), true),
+            (23, _) => (lines(0x05, 0x0a), false),
+            // VRC4b, VRC4d, VRC2c
+            (25, 1) => (lines(0x02, 0x01), false),
+            (25, 2) => (lines(0x08, 0x04), false),
+            (25, 3) => (lines(0x02, 0x01), true),
+            (25, _) => (lines(0x0a, 0x05), false),
+            _ => return None,
+        };
+
+        Some(Self {
+            lines,
+            vrc2,
+            chr_shift: (mapper == 22) as u8,
+
+            prg_banks: [0, 1],
+            prg_max: prg_banks * 2,
+            prg_swap: false,
+            ram_enabled: true,
+            chr_banks: [0; 8],
+
+            irq: VrcIrq::new(),
+
+            mirroring,
+        })
+    }
+
+    /// `addr` with the register select lines moved to bit 0 and 1
+    fn register(&self, addr: u16) -> u16 {
+        let a0 = addr & self.lines.a0 != 0;
+        let a1 = addr & self.lines.a1 != 0;
+        (addr & 0xf000) | (a1 as u16) << 1 | a0 as u16
+    }
+
+    fn set_chr_bank(&mut self, reg: u16, data: u8) {
+        let n = ((reg - 0xb000) >> 11) as usize | (reg as usize >> 1) & 1;
+        let bank = &mut self.chr_banks[n];
+        if reg & 1 == 0 {
+            *bank = (*bank & !0x0f) | (data as usize & 0x0f);
+        } else {
+            let bits = if self.vrc2 { 0x0f } else { 0x1f };
+            *bank = (*bank & 0x0f) | (data as usize & bits) << 4;
+        }
+    }
+}
+
+impl super::Mapper for Mapper021 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let second_last = self.prg_max - 2;
+        let bank = match (addr, self.prg_swap) {
+            (0x8000..=0x9fff, false) | (0xc000..=0xdfff, true) => self.prg_banks[0],
+            (0x8000..=0x9fff, true) | (0xc000..=0xdfff, false) => second_last,
+            (0xa000..=0xbfff, _) => self.prg_banks[1],
+            (0xe000..=0xffff, _) => self.prg_max - 1,
+            _ => return None,
+        };
+        Some(bank * 0x2000 + (addr as usize & 0x1fff))
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+        let reg = self.register(addr);
+        match reg {
+            0x8000..=0x8003 => self.prg_banks[0] = (data as usize & 0x1f) % self.prg_max,
+            0xa000..=0xa003 => self.prg_banks[1] = (data as usize & 0x1f) % self.prg_max,
+            0x9000..=0x9001 => {
+                self.mirroring = match data & if self.vrc2 { 0b01 } else { 0b11 } {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreen0,
+                    _ => Mirroring::SingleScreen1,
+                }
+            }
+            0x9002..=0x9003 if !self.vrc2 => {
+                self.ram_enabled = data.get_bit(0);
+                self.prg_swap = data.get_bit(1);
+            }
+            0xb000..=0xe003 => self.set_chr_bank(reg, data),
+            0xf000..=0xf001 if !self.vrc2 => self.irq.write_latch_nibble(reg == 0xf001, data),
+            0xf002 if !self.vrc2 => self.irq.write_control(data),
+            0xf003 if !self.vrc2 => self.irq.ack(),
+            _ => {}
+        }
+    }
+
+    fn prg_ram_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x6000..=0x7fff if self.ram_enabled => Some(addr as usize - 0x6000),
+            _ => None,
+        }
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize >> 10] >> self.chr_shift;
+        chr[(bank * 0x400 + (addr as usize & 0x3ff)) % chr.len()]
+    }
+
+    fn tick(&mut self) {
+        self.irq.tick();
+    }
+
+    fn poll_irq(&mut self) -> bool {
+        self.irq.pending()
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        self.prg_banks.iter().for_each(|&b| w.usize(b));
+        w.bool(self.prg_swap);
+        w.bool(self.ram_enabled);
+        self.chr_banks.iter().for_each(|&b| w.usize(b));
+        self.irq.save_state(w);
+        self.mirroring.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        for b in self.prg_banks.iter_mut() {
+            *b = r.usize()? % self.prg_max;
+        }
+        self.prg_swap = r.bool()?;
+        self.ram_enabled = r.bool()?;
+        for b in self.chr_banks.iter_mut() {
+            *b = r.usize()? & 0x1ff;
+        }
+        self.irq.load_state(r)?;
+        self.mirroring.load_state(r)
+    }
+}
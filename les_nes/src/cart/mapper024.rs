@@ -0,0 +1,172 @@
+use super::mapper021::VrcIrq;
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use audio::Vrc6Audio;
+use bit_field::BitField;
+
+mod audio;
+
+/// 024 and 026, Konami VRC6
+///
+/// 026 swaps the A0 and A1 lines.
+///
+/// 0x8000-0xbfff: 16 KB switchable PRG bank,
+/// 0xc000-0xdfff: 8 KB switchable PRG bank,
+/// 0xe000-0xffff: the last 8 KB PRG bank,
+/// 8 * 1 KB CHR banks, or 2 KB ones in some modes of $B003
+pub struct Mapper024 {
+    swap_lines: bool,
+
+    prg_16k: usize,
+    prg_8k: usize,
+    /// in 8 KB units
+    prg_max: usize,
+    chr_regs: [usize; 8],
+    /// $B003
+    ppu_mode: u8,
+    ram_enabled: bool,
+
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+
+impl Mapper024 {
+    pub fn new(prg_banks: usize, swap_lines: bool) -> Option<Self> {
+        if prg_banks == 0 {
+            return None;
+        }
+
+        Some(Self {
+            swap_lines,
+
+            prg_16k: 0,
+            prg_8k: 0,
+            prg_max: prg_banks * 2,
+            chr_regs: [0; 8],
+            ppu_mode: 0,
+            ram_enabled: false,
+
+            irq: VrcIrq::new(),
+            audio: Vrc6Audio::new(),
+        })
+    }
+
+    /// `addr` with the register select lines in bit 0 and 1
+    fn register(&self, addr: u16) -> u16 {
+        let reg = addr & 0xf003;
+        if self.swap_lines {
+            (reg & 0xf000) | (reg & 1) << 1 | (reg & 2) >> 1
+        } else {
+            reg
+        }
+    }
+
+    /// 1 KB bank of the pattern table address `addr`
+    fn chr_bank(&self, addr: u16) -> usize {
+        let slot = addr as usize >> 10;
+        // 2 KB banks take their low bit from A10
+        let wide = |reg: usize| (self.chr_regs[reg] & !1) | (slot & 1);
+        match (self.ppu_mode & 0b11, slot) {
+            (0, _) => self.chr_regs[slot],
+            (1, _) => wide(slot / 2),
+            (_, 0..=3) => self.chr_regs[slot],
+            (_, _) => wide(4 + (slot - 4) / 2),
+        }
+    }
+}
+
+impl super::Mapper for Mapper024 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let offset = match addr {
+            0x8000..=0xbfff => self.prg_16k * 0x4000 + (addr as usize & 0x3fff),
+            0xc000..=0xdfff => self.prg_8k * 0x2000 + (addr as usize & 0x1fff),
+            0xe000..=0xffff => (self.prg_max - 1) * 0x2000 + (addr as usize & 0x1fff),
+            _ => return None,
+        };
+        Some(offset)
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+        let reg = self.register(addr);
+        match reg {
+            0x8000..=0x8003 => self.prg_16k = (data as usize & 0x0f) % (self.prg_max / 2),
+            0xb003 => {
+                self.ppu_mode = data;
+                self.ram_enabled = data.get_bit(7);
+            }
+            0x9000..=0xb002 => self.audio.write(reg, data),
+            0xc000..=0xc003 => self.prg_8k = (data as usize & 0x1f) % self.prg_max,
+            0xd000..=0xe003 => {
+                let n = ((reg as usize - 0xd000) >> 12) * 4 + (reg as usize & 3);
+                self.chr_regs[n] = data as usize;
+            }
+            0xf000 => self.irq.write_latch(data),
+            0xf001 => self.irq.write_control(data),
+            0xf002 => self.irq.ack(),
+            _ => {}
+        }
+    }
+
+    fn prg_ram_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x6000..=0x7fff if self.ram_enabled => Some(addr as usize - 0x6000),
+            _ => None,
+        }
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[(self.chr_bank(addr) * 0x400 + (addr as usize & 0x3ff)) % chr.len()]
+    }
+
+    fn tick(&mut self) {
+        self.irq.tick();
+        self.audio.tick();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn audio_channels(&self) -> &'static [&'static str] {
+        &audio::CHANNELS
+    }
+
+    fn set_audio_channels(&mut self, enabled: &[bool]) {
+        self.audio.set_channels(enabled);
+    }
+
+    fn poll_irq(&mut self) -> bool {
+        self.irq.pending()
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.ppu_mode.get_bits(2..4) {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreen0,
+            _ => Mirroring::SingleScreen1,
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.prg_16k);
+        w.usize(self.prg_8k);
+        self.chr_regs.iter().for_each(|&b| w.usize(b));
+        w.u8(self.ppu_mode);
+        w.bool(self.ram_enabled);
+        self.irq.save_state(w);
+        self.audio.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.prg_16k = r.usize()? % (self.prg_max / 2);
+        self.prg_8k = r.usize()? % self.prg_max;
+        for b in self.chr_regs.iter_mut() {
+            *b = r.usize()? & 0xff;
+        }
+        self.ppu_mode = r.u8()?;
+        self.ram_enabled = r.bool()?;
+        self.irq.load_state(r)?;
+        self.audio.load_state(r)
+    }
+}
@@ -0,0 +1,252 @@
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+/// output per step of the 0-61 sum, about as loud as the 2A03 pulses
+const VOLUME: f32 = 0.0099;
+
+pub const CHANNELS: [&str; 3] = ["VRC6 Pulse1", "VRC6 Pulse2", "VRC6 Saw"];
+
+/// 12 bit period counting down every cpu cycle
+#[derive(Default)]
+struct Timer {
+    period: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl Timer {
+    /// $x001
+    fn write_low(&mut self, data: u8) {
+        self.period.set_bits(0..8, data as u16);
+    }
+
+    /// $x002, bit 7 enables the channel
+    fn write_high(&mut self, data: u8) {
+        self.period.set_bits(8..12, data as u16 & 0x0f);
+        self.enabled = data.get_bit(7);
+    }
+
+    /// true when the counter reloads, `shift` comes from $9003
+    fn tick(&mut self, shift: u8) -> bool {
+        if self.counter == 0 {
+            self.counter = self.period >> shift;
+            true
+        } else {
+            self.counter -= 1;
+            false
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.period);
+        w.u16(self.counter);
+        w.bool(self.enabled);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.period = r.u16()? & 0x0fff;
+        self.counter = r.u16()? & 0x0fff;
+        self.enabled = r.bool()?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Pulse {
+    timer: Timer,
+    volume: u8,
+    duty: u8,
+    /// constant volume, ignores the duty
+    mode: bool,
+    step: u8,
+}
+
+impl Pulse {
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.volume = data.get_bits(0..4);
+                self.duty = data.get_bits(4..7);
+                self.mode = data.get_bit(7);
+            }
+            1 => self.timer.write_low(data),
+            _ => {
+                self.timer.write_high(data);
+                if !self.timer.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self, shift: u8) {
+        if self.timer.enabled && self.timer.tick(shift) {
+            self.step = self.step.wrapping_sub(1) & 0x0f;
+        }
+    }
+
+    fn sample(&self) -> u8 {
+        if self.timer.enabled && (self.mode || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Default)]
+struct Saw {
+    timer: Timer,
+    rate: u8,
+    accumulator: u8,
+    /// 14 timer clocks make a period, the accumulator adds on every second one
+    step: u8,
+}
+
+impl Saw {
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => self.rate = data.get_bits(0..6),
+            1 => self.timer.write_low(data),
+            _ => {
+                self.timer.write_high(data);
+                if !self.timer.enabled {
+                    self.accumulator = 0;
+                    self.step = 0;
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self, shift: u8) {
+        if !(self.timer.enabled && self.timer.tick(shift)) {
+            return;
+        }
+
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step.is_multiple_of(2) {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn sample(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+/// two pulses with 8 duty cycles and a sawtooth
+pub struct Vrc6Audio {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    saw: Saw,
+    /// $9003
+    halt: bool,
+    shift: u8,
+    channels: [bool; 3],
+    output: f32,
+}
+
+impl Vrc6Audio {
+    pub fn new() -> Self {
+        Self {
+            pulse1: Pulse::default(),
+            pulse2: Pulse::default(),
+            saw: Saw::default(),
+            halt: false,
+            shift: 0,
+            channels: [true; 3],
+            output: 0.0,
+        }
+    }
+
+    /// `reg` is the normalized register, $9000-$B002
+    pub fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0x9003 => {
+                self.halt = data.get_bit(0);
+                self.shift = if data.get_bit(2) {
+                    8
+                } else if data.get_bit(1) {
+                    4
+                } else {
+                    0
+                };
+            }
+            0x9000..=0x9002 => self.pulse1.write(reg & 3, data),
+            0xa000..=0xa002 => self.pulse2.write(reg & 3, data),
+            0xb000..=0xb002 => self.saw.write(reg & 3, data),
+            _ => {}
+        }
+    }
+
+    pub fn tick(&mut self) {
+        if !self.halt {
+            self.pulse1.tick(self.shift);
+            self.pulse2.tick(self.shift);
+            self.saw.tick(self.shift);
+        }
+
+        let samples = [
+            self.pulse1.sample(),
+            self.pulse2.sample(),
+            self.saw.sample(),
+        ];
+        let sum: u8 = samples
+            .iter()
+            .zip(self.channels)
+            .map(|(&s, on)| s * on as u8)
+            .sum();
+        self.output = sum as f32 * VOLUME;
+    }
+
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    pub fn set_channels(&mut self, enabled: &[bool]) {
+        for (c, &e) in self.channels.iter_mut().zip(enabled) {
+            *c = e;
+        }
+    }
+}
+
+impl Snapshot for Vrc6Audio {
+    fn save_state(&self, w: &mut StateWriter) {
+        for p in [&self.pulse1, &self.pulse2] {
+            p.timer.save_state(w);
+            w.u8(p.volume);
+            w.u8(p.duty);
+            w.bool(p.mode);
+            w.u8(p.step);
+        }
+        self.saw.timer.save_state(w);
+        w.u8(self.saw.rate);
+        w.u8(self.saw.accumulator);
+        w.u8(self.saw.step);
+        w.bool(self.halt);
+        w.u8(self.shift);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        for p in [&mut self.pulse1, &mut self.pulse2] {
+            p.timer.load_state(r)?;
+            p.volume = r.u8()? & 0x0f;
+            p.duty = r.u8()? & 0b111;
+            p.mode = r.bool()?;
+            p.step = r.u8()? & 0x0f;
+        }
+        self.saw.timer.load_state(r)?;
+        self.saw.rate = r.u8()? & 0x3f;
+        self.saw.accumulator = r.u8()?;
+        self.saw.step = r.u8()? % 14;
+        self.halt = r.bool()?;
+        self.shift = match r.u8()? {
+            s @ (0 | 4 | 8) => s,
+            _ => 0,
+        };
+        Ok(())
+    }
+}
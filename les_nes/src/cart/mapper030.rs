@@ -0,0 +1,211 @@
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+/// SST39SF040 software id, manufacturer and device
+const FLASH_ID: [u8; 2] = [0xbf, 0xb7];
+const FLASH_SECTOR_SIZE: usize = 0x1000;
+
+/// where the SST39SF040 is in its command sequences
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flash {
+    Idle,
+    /// $5555 = $AA
+    Unlock1,
+    /// $2AAA = $55
+    Unlock2,
+    /// $5555 = $A0, the next write programs a byte
+    Program,
+    /// $5555 = $80
+    Erase,
+    EraseUnlock1,
+    EraseUnlock2,
+}
+
+/// 030, UNROM-512
+///
+/// 0x8000-0xbfff: 16 KB switchable PRG banks,
+/// 0xc000-0xffff: the last 16 KB PRG bank,
+/// 4 * 8 KB switchable CHR RAM banks.
+/// a four screen header stands for the board with switchable one screen mirroring.
+///
+/// with the battery bit the PRG is a flash the game saves to, commands are written
+/// to 0x8000-0xbfff and the bank register moves to 0xc000-0xffff.
+pub struct Mapper030 {
+    prg_bank: usize,
+    prg_banks: usize,
+    chr_bank: usize,
+    /// bit 7 of the bank register picks the nametable
+    one_screen: bool,
+
+    flashable: bool,
+    flash: Flash,
+    software_id: bool,
+    flash_dirty: bool,
+
+    mirroring: Mirroring,
+}
+
+impl Mapper030 {
+    pub fn new(mirroring: Mirroring, prg_banks: usize, flashable: bool) -> Option<Self> {
+        if !(1..=32).contains(&prg_banks) {
+            return None;
+        }
+
+        let one_screen = mirroring == Mirroring::FourScreen;
+        Some(Self {
+            prg_bank: 0,
+            prg_banks,
+            chr_bank: 0,
+            one_screen,
+
+            flashable,
+            flash: Flash::Idle,
+            software_id: false,
+            flash_dirty: false,
+
+            mirroring: if one_screen {
+                Mirroring::SingleScreen0
+            } else {
+                mirroring
+            },
+        })
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        self.chr_bank * 0x2000 + addr as usize
+    }
+
+    fn write_bank(&mut self, data: u8) {
+        self.prg_bank = data.get_bits(0..5) as usize % self.prg_banks;
+        self.chr_bank = data.get_bits(5..7) as usize;
+        if self.one_screen {
+            self.mirroring = if data.get_bit(7) {
+                Mirroring::SingleScreen1
+            } else {
+                Mirroring::SingleScreen0
+            };
+        }
+    }
+
+    /// a write to 0x8000-0xbfff, `offset` is the flash address
+    fn write_flash(&mut self, prg: &mut [u8], offset: usize, data: u8) {
+        // the chip only decodes A0-A14 for commands
+        let cmd = (offset & 0x7fff, data);
+        self.flash = match (self.flash, cmd) {
+            (Flash::Program, _) => {
+                // programming can only clear bits
+                prg[offset] &= data;
+                self.flash_dirty = true;
+                Flash::Idle
+            }
+            (_, (_, 0xf0)) => {
+                self.software_id = false;
+                Flash::Idle
+            }
+            (Flash::Idle, (0x5555, 0xaa)) => Flash::Unlock1,
+            (Flash::Unlock1, (0x2aaa, 0x55)) => Flash::Unlock2,
+            (Flash::Unlock2, (0x5555, 0xa0)) => Flash::Program,
+            (Flash::Unlock2, (0x5555, 0x80)) => Flash::Erase,
+            (Flash::Unlock2, (0x5555, 0x90)) => {
+                self.software_id = true;
+                Flash::Idle
+            }
+            (Flash::Erase, (0x5555, 0xaa)) => Flash::EraseUnlock1,
+            (Flash::EraseUnlock1, (0x2aaa, 0x55)) => Flash::EraseUnlock2,
+            (Flash::EraseUnlock2, (0x5555, 0x10)) => {
+                prg.fill(0xff);
+                self.flash_dirty = true;
+                Flash::Idle
+            }
+            (Flash::EraseUnlock2, (_, 0x30)) => {
+                let sector = offset & !(FLASH_SECTOR_SIZE - 1);
+                prg[sector..sector + FLASH_SECTOR_SIZE].fill(0xff);
+                self.flash_dirty = true;
+                Flash::Idle
+            }
+            _ => Flash::Idle,
+        };
+    }
+}
+
+impl super::Mapper for Mapper030 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xbfff => Some(addr as usize - 0x8000 + self.prg_bank * 0x4000),
+            0xc000..=0xffff => Some(addr as usize - 0xc000 + (self.prg_banks - 1) * 0x4000),
+            _ => None,
+        }
+    }
+
+    fn read_prg(&self, prg: &[u8], addr: u16) -> u8 {
+        match self.prg_offset(addr) {
+            Some(i) if self.software_id => FLASH_ID[i & 1],
+            Some(i) => prg[i],
+            None => 0,
+        }
+    }
+
+    fn write_prg(&mut self, prg: &mut [u8], addr: u16, data: u8) {
+        match addr {
+            0x8000..=0xbfff if self.flashable => {
+                let offset = addr as usize - 0x8000 + self.prg_bank * 0x4000;
+                self.write_flash(prg, offset, data);
+            }
+            _ => self.write_bank(data),
+        }
+    }
+
+    fn prg_ram_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
+    fn prg_flash(&self) -> bool {
+        self.flashable
+    }
+
+    fn poll_flash_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.flash_dirty)
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[self.chr_offset(addr) % chr.len()]
+    }
+
+    fn write_chr(&mut self, chr: &mut [u8], addr: u16, data: u8) {
+        let i = self.chr_offset(addr) % chr.len();
+        chr[i] = data;
+    }
+
+    fn min_chr_ram_size(&self) -> usize {
+        0x8000
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.prg_bank);
+        w.usize(self.chr_bank);
+        w.u8(self.flash as u8);
+        w.bool(self.software_id);
+        self.mirroring.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.prg_bank = r.usize()? % self.prg_banks;
+        self.chr_bank = r.usize()? & 0b11;
+        self.flash = match r.u8()? {
+            1 => Flash::Unlock1,
+            2 => Flash::Unlock2,
+            3 => Flash::Program,
+            4 => Flash::Erase,
+            5 => Flash::EraseUnlock1,
+            6 => Flash::EraseUnlock2,
+            _ => Flash::Idle,
+        };
+        self.software_id = r.bool()?;
+        self.mirroring.load_state(r)
+    }
+}
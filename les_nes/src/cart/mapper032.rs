@@ -0,0 +1,103 @@
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+/// 032, Irem G-101
+///
+/// 0x8000-0x9fff: 8 KB switchable PRG bank, or the second last bank in prg mode 1,
+/// 0xa000-0xbfff: 8 KB switchable PRG bank,
+/// 0xc000-0xdfff: the second last 8 KB PRG bank, or the $8000 bank in prg mode 1,
+/// 0xe000-0xffff: the last 8 KB PRG bank,
+/// 8 * 1 KB CHR banks at $B000-$B007.
+/// $9000 is `.... ..PM`, Major League (submapper 1) ignores it and is single screen.
+pub struct Mapper032 {
+    prg_banks: [usize; 2],
+    /// in 8 KB units
+    prg_max: usize,
+    prg_mode: bool,
+    chr_banks: [u8; 8],
+    fixed_mirroring: bool,
+
+    mirroring: Mirroring,
+}
+
+impl Mapper032 {
+    pub fn new(mirroring: Mirroring, prg_banks: usize, submapper: u8) -> Option<Self> {
+        if prg_banks == 0 {
+            return None;
+        }
+
+        let fixed_mirroring = submapper == 1;
+        Some(Self {
+            prg_banks: [0; 2],
+            prg_max: prg_banks * 2,
+            prg_mode: false,
+            chr_banks: [0; 8],
+            fixed_mirroring,
+
+            mirroring: if fixed_mirroring {
+                Mirroring::SingleScreen0
+            } else {
+                mirroring
+            },
+        })
+    }
+}
+
+impl super::Mapper for Mapper032 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let second_last = self.prg_max - 2;
+        let bank = match addr {
+            0x8000..=0x9fff if self.prg_mode => second_last,
+            0x8000..=0x9fff => self.prg_banks[0],
+            0xa000..=0xbfff => self.prg_banks[1],
+            0xc000..=0xdfff if self.prg_mode => self.prg_banks[0],
+            0xc000..=0xdfff => second_last,
+            0xe000..=0xffff => self.prg_max - 1,
+            _ => return None,
+        };
+        Some(bank * 0x2000 + (addr as usize & 0x1fff))
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+        match addr & 0xf000 {
+            0x8000 => self.prg_banks[0] = (data as usize & 0x1f) % self.prg_max,
+            0x9000 if !self.fixed_mirroring => {
+                self.prg_mode = data.get_bit(1);
+                self.mirroring = if data.get_bit(0) {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+            }
+            0xa000 => self.prg_banks[1] = (data as usize & 0x1f) % self.prg_max,
+            0xb000 => self.chr_banks[addr as usize & 7] = data,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize >> 10] as usize;
+        chr[(bank * 0x400 + (addr as usize & 0x3ff)) % chr.len()]
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        self.prg_banks.iter().for_each(|&b| w.usize(b));
+        w.bool(self.prg_mode);
+        w.bytes(&self.chr_banks);
+        self.mirroring.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        for b in self.prg_banks.iter_mut() {
+            *b = r.usize()? % self.prg_max;
+        }
+        self.prg_mode = r.bool()?;
+        r.bytes(&mut self.chr_banks)?;
+        self.mirroring.load_state(r)
+    }
+}
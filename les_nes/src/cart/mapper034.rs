@@ -0,0 +1,107 @@
+use super::Mirroring;
+use crate::state::{StateReader, StateResult, StateWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Board {
+    /// 32 KB PRG banks switched at 0x8000-0xffff, 8 KB CHR RAM
+    Bnrom,
+    /// 32 KB PRG bank at 0x7ffd, 4 KB CHR banks at 0x7ffe and 0x7fff
+    Nina001,
+}
+
+/// 034, BNROM and NINA-001
+///
+/// both boards share the number, submapper 1 is NINA-001 and 2 is BNROM.
+/// old headers are told apart by NINA-001 having more than 8 KB of CHR ROM.
+pub struct Mapper034 {
+    board: Board,
+    prg_bank: usize,
+    prg_banks: usize,
+    chr_banks: [usize; 2],
+    /// in 4 KB units
+    chr_max: usize,
+
+    mirroring: Mirroring,
+}
+
+impl Mapper034 {
+    pub fn new(
+        mirroring: Mirroring,
+        prg_banks: usize,
+        chr_banks: usize,
+        submapper: u8,
+    ) -> Option<Self> {
+        if !(prg_banks >= 2 && prg_banks.is_multiple_of(2)) {
+            return None;
+        }
+
+        let board = match submapper {
+            1 => Board::Nina001,
+            2 => Board::Bnrom,
+            _ if chr_banks > 1 => Board::Nina001,
+            _ => Board::Bnrom,
+        };
+        if board == Board::Nina001 && chr_banks == 0 {
+            return None;
+        }
+
+        Some(Self {
+            board,
+            prg_bank: 0,
+            prg_banks: prg_banks / 2,
+            chr_banks: [0, 1],
+            chr_max: chr_banks * 2,
+
+            mirroring,
+        })
+    }
+}
+
+impl super::Mapper for Mapper034 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        Some(self.prg_bank * 0x8000 + (addr as usize & 0x7fff))
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], _addr: u16, data: u8) {
+        if self.board == Board::Bnrom {
+            self.prg_bank = data as usize % self.prg_banks;
+        }
+    }
+
+    fn write_low(&mut self, addr: u16, data: u8) {
+        if self.board != Board::Nina001 {
+            return;
+        }
+
+        match addr {
+            0x7ffd => self.prg_bank = (data as usize & 0b1) % self.prg_banks,
+            0x7ffe => self.chr_banks[0] = (data as usize & 0b1111) % self.chr_max,
+            0x7fff => self.chr_banks[1] = (data as usize & 0b1111) % self.chr_max,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize >> 12];
+        chr[bank * 0x1000 + (addr as usize & 0x0fff)]
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.prg_bank);
+        for &b in &self.chr_banks {
+            w.usize(b);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.prg_bank = r.usize()? % self.prg_banks;
+        for b in self.chr_banks.iter_mut() {
+            *b = r.usize()? % self.chr_max.max(1);
+        }
+        Ok(())
+    }
+}
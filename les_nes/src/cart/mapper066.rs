@@ -0,0 +1,66 @@
+use super::Mirroring;
+use crate::state::{StateReader, StateResult, StateWriter};
+
+/// 066, GxROM
+///
+/// 0x8000-0xffff: 32 KB switchable PRG banks,
+/// 8 KB switchable CHR banks,
+/// one register at 0x8000-0xffff: `..PP..CC`
+/// GNROM and MHROM have bus conflicts, NES 2.0 defines no submappers to turn them off.
+pub struct Mapper066 {
+    prg_bank: usize,
+    prg_banks: usize,
+    chr_bank: usize,
+    chr_banks: usize,
+
+    mirroring: Mirroring,
+}
+
+impl Mapper066 {
+    pub fn new(mirroring: Mirroring, prg_banks: usize, chr_banks: usize) -> Option<Self> {
+        if !(prg_banks >= 2 && prg_banks.is_multiple_of(2) && chr_banks >= 1) {
+            return None;
+        }
+
+        Some(Self {
+            prg_bank: 0,
+            prg_banks: prg_banks / 2,
+            chr_bank: 0,
+            chr_banks,
+
+            mirroring,
+        })
+    }
+}
+
+impl super::Mapper for Mapper066 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        Some(self.prg_bank * 0x8000 + (addr as usize & 0x7fff))
+    }
+
+    fn write_prg(&mut self, prg: &mut [u8], addr: u16, data: u8) {
+        // the rom drives the bus too, only bits that are 1 in both survive
+        let data = data & self.read_prg(prg, addr);
+        self.prg_bank = (data as usize >> 4 & 0b11) % self.prg_banks;
+        self.chr_bank = (data as usize & 0b11) % self.chr_banks;
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[self.chr_bank * 0x2000 + addr as usize]
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.prg_bank);
+        w.usize(self.chr_bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.prg_bank = r.usize()? % self.prg_banks;
+        self.chr_bank = r.usize()? % self.chr_banks;
+        Ok(())
+    }
+}
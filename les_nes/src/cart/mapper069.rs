@@ -0,0 +1,201 @@
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use audio::Sunsoft5bAudio;
+use bit_field::BitField;
+
+mod audio;
+
+/// 069, Sunsoft FME-7 and 5B
+///
+/// 0x6000-0x7fff: 8 KB switchable PRG ROM or RAM bank,
+/// 0x8000-0xdfff: 3 * 8 KB switchable PRG banks,
+/// 0xe000-0xffff: the last 8 KB PRG bank,
+/// 8 * 1 KB CHR banks, all picked by a command at $8000 and its parameter at $A000.
+/// 5B adds the audio, which doesn't hurt FME-7 games that never write it.
+pub struct Mapper069 {
+    command: u8,
+    /// $6000, $8000, $A000 and $C000
+    prg_banks: [usize; 4],
+    /// in 8 KB units
+    prg_max: usize,
+    /// $6000-$7FFF is ram instead of rom
+    ram_selected: bool,
+    ram_enabled: bool,
+    chr_banks: [usize; 8],
+
+    irq_counter: u16,
+    irq_enabled: bool,
+    counter_enabled: bool,
+    irq_pending: bool,
+
+    audio: Sunsoft5bAudio,
+
+    mirroring: Mirroring,
+}
+
+impl Mapper069 {
+    pub fn new(mirroring: Mirroring, prg_banks: usize) -> Option<Self> {
+        if prg_banks == 0 {
+            return None;
+        }
+
+        Some(Self {
+            command: 0,
+            prg_banks: [0; 4],
+            prg_max: prg_banks * 2,
+            ram_selected: false,
+            ram_enabled: false,
+            chr_banks: [0; 8],
+
+            irq_counter: 0,
+            irq_enabled: false,
+            counter_enabled: false,
+            irq_pending: false,
+
+            audio: Sunsoft5bAudio::new(),
+
+            mirroring,
+        })
+    }
+
+    fn write_parameter(&mut self, data: u8) {
+        match self.command {
+            n @ 0x0..=0x7 => self.chr_banks[n as usize] = data as usize,
+            0x8 => {
+                self.prg_banks[0] = (data as usize & 0x3f) % self.prg_max;
+                self.ram_selected = data.get_bit(6);
+                self.ram_enabled = data.get_bit(7);
+            }
+            n @ 0x9..=0xb => {
+                self.prg_banks[n as usize - 0x8] = (data as usize & 0x3f) % self.prg_max;
+            }
+            0xc => {
+                self.mirroring = match data & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreen0,
+                    _ => Mirroring::SingleScreen1,
+                }
+            }
+            0xd => {
+                self.irq_enabled = data.get_bit(0);
+                self.counter_enabled = data.get_bit(7);
+                self.irq_pending = false;
+            }
+            0xe => {
+                self.irq_counter.set_bits(0..8, data as u16);
+            }
+            _ => {
+                self.irq_counter.set_bits(8..16, data as u16);
+            }
+        }
+    }
+}
+
+impl super::Mapper for Mapper069 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let bank = match addr {
+            0x6000..=0x7fff if !self.ram_selected => self.prg_banks[0],
+            0x8000..=0xdfff => self.prg_banks[(addr as usize - 0x6000) >> 13],
+            0xe000..=0xffff => self.prg_max - 1,
+            _ => return None,
+        };
+        Some(bank * 0x2000 + (addr as usize & 0x1fff))
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+        match addr {
+            0x8000..=0x9fff => self.command = data & 0x0f,
+            0xa000..=0xbfff => self.write_parameter(data),
+            0xc000..=0xdfff => self.audio.write_address(data),
+            _ => self.audio.write_data(data),
+        }
+    }
+
+    /// the ram bank is only 8 KB, the bank number is ignored
+    fn prg_ram_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x6000..=0x7fff if self.ram_selected && self.ram_enabled => {
+                Some(addr as usize - 0x6000)
+            }
+            _ => None,
+        }
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize >> 10];
+        chr[(bank * 0x400 + (addr as usize & 0x3ff)) % chr.len()]
+    }
+
+    fn write_chr(&mut self, chr: &mut [u8], addr: u16, data: u8) {
+        let bank = self.chr_banks[addr as usize >> 10];
+        let i = (bank * 0x400 + (addr as usize & 0x3ff)) % chr.len();
+        chr[i] = data;
+    }
+
+    fn tick(&mut self) {
+        if self.counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xffff && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.tick();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn audio_channels(&self) -> &'static [&'static str] {
+        &audio::CHANNELS
+    }
+
+    fn set_audio_channels(&mut self, enabled: &[bool]) {
+        self.audio.set_channels(enabled);
+    }
+
+    fn poll_irq(&mut self) -> bool {
+        self.irq_pending
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.command);
+        self.prg_banks.iter().for_each(|&b| w.usize(b));
+        w.bool(self.ram_selected);
+        w.bool(self.ram_enabled);
+        self.chr_banks.iter().for_each(|&b| w.usize(b));
+
+        w.u16(self.irq_counter);
+        w.bool(self.irq_enabled);
+        w.bool(self.counter_enabled);
+        w.bool(self.irq_pending);
+
+        self.audio.save_state(w);
+        self.mirroring.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.command = r.u8()? & 0x0f;
+        for b in self.prg_banks.iter_mut() {
+            *b = r.usize()? % self.prg_max;
+        }
+        self.ram_selected = r.bool()?;
+        self.ram_enabled = r.bool()?;
+        for b in self.chr_banks.iter_mut() {
+            *b = r.usize()? & 0xff;
+        }
+
+        self.irq_counter = r.u16()?;
+        self.irq_enabled = r.bool()?;
+        self.counter_enabled = r.bool()?;
+        self.irq_pending = r.bool()?;
+
+        self.audio.load_state(r)?;
+        self.mirroring.load_state(r)
+    }
+}
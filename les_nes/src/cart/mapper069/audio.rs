@@ -0,0 +1,273 @@
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+pub const CHANNELS: [&str; 3] = ["5B Square A", "5B Square B", "5B Square C"];
+
+/// a full volume channel, about as loud as a 2A03 pulse
+const VOLUME: f32 = 0.15;
+
+/// cpu cycles per tick of the tone and noise counters
+const TONE_DIVIDER: usize = 16;
+/// cpu cycles per tick of the envelope counter, 32 steps a ramp
+const ENVELOPE_DIVIDER: usize = 8;
+
+lazy_static::lazy_static! {
+    /// 32 levels 1.5 dB apart, the 4 bit volumes use every other one
+    static ref LEVELS: [f32; 32] = {
+        let mut table = [0.0f32; 32];
+        table.iter_mut().enumerate().skip(1).for_each(|(i, t)| {
+            *t = 10f32.powf(-((31 - i) as f32 * 1.5) / 20.0);
+        });
+        table
+    };
+}
+
+#[derive(Default)]
+struct Tone {
+    period: u16,
+    counter: u16,
+    output: bool,
+    /// 4 bit volume, or the envelope if bit 4 is set
+    volume: u8,
+}
+
+impl Tone {
+    fn tick(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.period.max(1) {
+            self.counter = 0;
+            self.output = !self.output;
+        }
+    }
+}
+
+/// the AY-3-8910 like sound of Sunsoft 5B, three squares sharing a noise and an envelope
+pub struct Sunsoft5bAudio {
+    address: u8,
+    tones: [Tone; 3],
+    /// bit 0-2 disable the tones, bit 3-5 the noise, per channel
+    mixer: u8,
+
+    noise_period: u8,
+    noise_counter: u8,
+    noise_half: bool,
+    lfsr: u32,
+
+    env_period: u16,
+    env_counter: u16,
+    env_shape: u8,
+    /// 0-31 within the current ramp
+    env_step: u8,
+    env_holding: bool,
+    /// the ramp goes up
+    env_attack: bool,
+
+    cycles: usize,
+    channels: [bool; 3],
+    output: f32,
+}
+
+impl Sunsoft5bAudio {
+    pub fn new() -> Self {
+        Self {
+            address: 0,
+            tones: Default::default(),
+            mixer: 0xff,
+
+            noise_period: 0,
+            noise_counter: 0,
+            noise_half: false,
+            lfsr: 1,
+
+            env_period: 0,
+            env_counter: 0,
+            env_shape: 0,
+            env_step: 0,
+            env_holding: true,
+            env_attack: false,
+
+            cycles: 0,
+            channels: [true; 3],
+            output: 0.0,
+        }
+    }
+
+    /// $C000
+    pub fn write_address(&mut self, data: u8) {
+        self.address = data;
+    }
+
+    /// $E000
+    pub fn write_data(&mut self, data: u8) {
+        match self.address {
+            reg @ 0x00..=0x05 => {
+                let tone = &mut self.tones[reg as usize / 2];
+                if reg.is_multiple_of(2) {
+                    tone.period.set_bits(0..8, data as u16);
+                } else {
+                    tone.period.set_bits(8..12, data as u16 & 0x0f);
+                }
+            }
+            0x06 => self.noise_period = data & 0x1f,
+            0x07 => self.mixer = data,
+            reg @ 0x08..=0x0a => self.tones[reg as usize - 8].volume = data & 0x1f,
+            0x0b => {
+                self.env_period.set_bits(0..8, data as u16);
+            }
+            0x0c => {
+                self.env_period.set_bits(8..16, data as u16);
+            }
+            0x0d => {
+                self.env_shape = data & 0x0f;
+                self.env_attack = data.get_bit(2);
+                self.env_step = 0;
+                self.env_counter = 0;
+                self.env_holding = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn tick_noise(&mut self) {
+        // the noise runs at half the rate of the tones
+        self.noise_half = !self.noise_half;
+        if !self.noise_half {
+            return;
+        }
+
+        self.noise_counter += 1;
+        if self.noise_counter >= self.noise_period.max(1) {
+            self.noise_counter = 0;
+            let bit = (self.lfsr ^ (self.lfsr >> 3)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (bit << 16);
+        }
+    }
+
+    fn tick_envelope(&mut self) {
+        self.env_counter += 1;
+        if self.env_counter < self.env_period.max(1) {
+            return;
+        }
+        self.env_counter = 0;
+
+        if self.env_holding {
+            return;
+        }
+        if self.env_step < 31 {
+            self.env_step += 1;
+            return;
+        }
+
+        // the end of a ramp
+        let [hold, alternate, _, cont] = [0, 1, 2, 3].map(|i| self.env_shape.get_bit(i));
+        if !cont {
+            self.env_holding = true;
+            self.env_attack = false;
+            self.env_step = 31;
+        } else if hold {
+            self.env_holding = true;
+            self.env_attack ^= alternate;
+        } else {
+            self.env_step = 0;
+            self.env_attack ^= alternate;
+        }
+    }
+
+    fn envelope_level(&self) -> usize {
+        if self.env_attack {
+            self.env_step as usize
+        } else {
+            31 - self.env_step as usize
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+        if self.cycles.is_multiple_of(TONE_DIVIDER) {
+            self.tones.iter_mut().for_each(Tone::tick);
+            self.tick_noise();
+        }
+        if self.cycles.is_multiple_of(ENVELOPE_DIVIDER) {
+            self.tick_envelope();
+        }
+
+        let noise = self.lfsr & 1 != 0;
+        let mut output = 0.0;
+        for (i, tone) in self.tones.iter().enumerate() {
+            let tone_on = tone.output || self.mixer.get_bit(i);
+            let noise_on = noise || self.mixer.get_bit(i + 3);
+            if !(tone_on && noise_on && self.channels[i]) {
+                continue;
+            }
+
+            let level = if tone.volume.get_bit(4) {
+                self.envelope_level()
+            } else {
+                match tone.volume & 0x0f {
+                    0 => 0,
+                    v => v as usize * 2 + 1,
+                }
+            };
+            output += LEVELS[level];
+        }
+        self.output = output * VOLUME;
+    }
+
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+
+    pub fn set_channels(&mut self, enabled: &[bool]) {
+        for (c, &e) in self.channels.iter_mut().zip(enabled) {
+            *c = e;
+        }
+    }
+}
+
+impl Snapshot for Sunsoft5bAudio {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.address);
+        for t in self.tones.iter() {
+            w.u16(t.period);
+            w.u16(t.counter);
+            w.bool(t.output);
+            w.u8(t.volume);
+        }
+        w.u8(self.mixer);
+        w.u8(self.noise_period);
+        w.u8(self.noise_counter);
+        w.bool(self.noise_half);
+        w.usize(self.lfsr as usize);
+        w.u16(self.env_period);
+        w.u16(self.env_counter);
+        w.u8(self.env_shape);
+        w.u8(self.env_step);
+        w.bool(self.env_holding);
+        w.bool(self.env_attack);
+        w.usize(self.cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.address = r.u8()?;
+        for t in self.tones.iter_mut() {
+            t.period = r.u16()? & 0x0fff;
+            t.counter = r.u16()? & 0x0fff;
+            t.output = r.bool()?;
+            t.volume = r.u8()? & 0x1f;
+        }
+        self.mixer = r.u8()?;
+        self.noise_period = r.u8()? & 0x1f;
+        self.noise_counter = r.u8()? & 0x1f;
+        self.noise_half = r.bool()?;
+        // a zero lfsr would never shift out a one
+        self.lfsr = (r.usize()? as u32 & 0x1ffff).max(1);
+        self.env_period = r.u16()?;
+        self.env_counter = r.u16()?;
+        self.env_shape = r.u8()? & 0x0f;
+        self.env_step = r.u8()? & 0x1f;
+        self.env_holding = r.bool()?;
+        self.env_attack = r.bool()?;
+        self.cycles = r.usize()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+/// 071, Camerica/Codemasters BF909x
+///
+/// 0x8000-0xbfff: 16 KB switchable PRG banks,
+/// 0xc000-0xffff: 16 KB PRG bank (fixed to the last bank),
+/// 8 KB CHR RAM,
+/// 0xc000-0xffff: `.... PPPP`
+/// 0x8000-0x9fff: `...M ....`, single screen select of the BF9097 on Fire Hawk (submapper 1).
+/// iNES dumps get it from the first write to $9000-$9FFF, which only Fire Hawk does.
+pub struct Mapper071 {
+    prg_bank: usize,
+    prg_banks: usize,
+    single_screen: bool,
+
+    mirroring: Mirroring,
+}
+
+impl Mapper071 {
+    pub fn new(mirroring: Mirroring, prg_banks: usize, submapper: u8) -> Option<Self> {
+        if prg_banks == 0 {
+            return None;
+        }
+
+        Some(Self {
+            prg_bank: 0,
+            prg_banks,
+            single_screen: submapper == 1,
+
+            mirroring,
+        })
+    }
+}
+
+impl super::Mapper for Mapper071 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x8000..=0xbfff => Some(addr as usize - 0x8000 + self.prg_bank * 0x4000),
+            0xc000..=0xffff => Some(addr as usize - 0xc000 + (self.prg_banks - 1) * 0x4000),
+            _ => None,
+        }
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+        match addr {
+            0x8000..=0x9fff => {
+                self.single_screen |= addr >= 0x9000;
+                if self.single_screen {
+                    self.mirroring = if data.get_bit(4) {
+                        Mirroring::SingleScreen1
+                    } else {
+                        Mirroring::SingleScreen0
+                    };
+                }
+            }
+            0xc000..=0xffff => self.prg_bank = (data as usize & 0x0f) % self.prg_banks,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[addr as usize]
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.prg_bank);
+        w.bool(self.single_screen);
+        self.mirroring.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.prg_bank = r.usize()? % self.prg_banks;
+        self.single_screen = r.bool()?;
+        self.mirroring.load_state(r)
+    }
+}
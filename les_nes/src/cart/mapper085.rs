@@ -0,0 +1,196 @@
+use super::mapper021::VrcIrq;
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+use opll::Opll;
+
+mod opll;
+
+/// output per step of a channel, a full volume channel is a bit louder than a 2A03 pulse
+const VOLUME: f32 = 0.15 / 4096.0;
+
+const CHANNEL_NAMES: [&str; opll::CHANNELS] = [
+    "VRC7 FM1", "VRC7 FM2", "VRC7 FM3", "VRC7 FM4", "VRC7 FM5", "VRC7 FM6",
+];
+
+/// 085, Konami VRC7
+///
+/// VRC7a selects the second register of a pair with A4, VRC7b with A3,
+/// submapper 2 and 1 respectively. without one both are decoded.
+///
+/// 0x8000-0xdfff: 3 * 8 KB switchable PRG banks,
+/// 0xe000-0xffff: the last 8 KB PRG bank,
+/// 8 * 1 KB CHR banks
+pub struct Mapper085 {
+    /// the line of the second register
+    line: u16,
+
+    prg_banks: [usize; 3],
+    /// in 8 KB units
+    prg_max: usize,
+    chr_banks: [usize; 8],
+    ram_enabled: bool,
+    /// the audio is held in reset
+    silenced: bool,
+
+    irq: VrcIrq,
+    opll: Opll,
+    channels: [bool; opll::CHANNELS],
+
+    mirroring: Mirroring,
+}
+
+impl Mapper085 {
+    pub fn new(prg_banks: usize, submapper: u8) -> Option<Self> {
+        if prg_banks == 0 {
+            return None;
+        }
+
+        let line = match submapper {
+            1 => 0x08,
+            2 => 0x10,
+            _ => 0x18,
+        };
+        Some(Self {
+            line,
+
+            prg_banks: [0; 3],
+            prg_max: prg_banks * 2,
+            chr_banks: [0; 8],
+            ram_enabled: false,
+            silenced: false,
+
+            irq: VrcIrq::new(),
+            opll: Opll::new(),
+            channels: [true; opll::CHANNELS],
+
+            mirroring: Mirroring::Vertical,
+        })
+    }
+}
+
+impl super::Mapper for Mapper085 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let bank = match addr {
+            0x8000..=0xdfff => self.prg_banks[(addr as usize - 0x8000) >> 13],
+            0xe000..=0xffff => self.prg_max - 1,
+            _ => return None,
+        };
+        Some(bank * 0x2000 + (addr as usize & 0x1fff))
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+        // the audio ports don't go through the line select
+        match addr & 0xf030 {
+            0x9010 => return self.opll.write_address(data),
+            0x9030 => return self.opll.write_data(data),
+            _ => {}
+        }
+
+        let second = addr & self.line != 0;
+        match (addr & 0xf000, second) {
+            (0x8000, false) => self.prg_banks[0] = (data as usize & 0x3f) % self.prg_max,
+            (0x8000, true) => self.prg_banks[1] = (data as usize & 0x3f) % self.prg_max,
+            (0x9000, false) => self.prg_banks[2] = (data as usize & 0x3f) % self.prg_max,
+            (0xa000..=0xd000, _) => {
+                let n = ((addr as usize & 0xf000) - 0xa000) >> 11 | second as usize;
+                self.chr_banks[n] = data as usize;
+            }
+            (0xe000, false) => {
+                self.mirroring = match data & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreen0,
+                    _ => Mirroring::SingleScreen1,
+                };
+                self.silenced = data.get_bit(6);
+                if self.silenced {
+                    self.opll = Opll::new();
+                }
+                self.ram_enabled = data.get_bit(7);
+            }
+            (0xe000, true) => self.irq.write_latch(data),
+            (0xf000, false) => self.irq.write_control(data),
+            (0xf000, true) => self.irq.ack(),
+            _ => {}
+        }
+    }
+
+    fn prg_ram_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x6000..=0x7fff if self.ram_enabled => Some(addr as usize - 0x6000),
+            _ => None,
+        }
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize >> 10];
+        chr[(bank * 0x400 + (addr as usize & 0x3ff)) % chr.len()]
+    }
+
+    fn write_chr(&mut self, chr: &mut [u8], addr: u16, data: u8) {
+        let bank = self.chr_banks[addr as usize >> 10];
+        let i = (bank * 0x400 + (addr as usize & 0x3ff)) % chr.len();
+        chr[i] = data;
+    }
+
+    fn tick(&mut self) {
+        self.irq.tick();
+        if !self.silenced {
+            self.opll.tick();
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        let sum: i32 = self
+            .opll
+            .outputs()
+            .iter()
+            .zip(self.channels)
+            .map(|(&s, on)| s * on as i32)
+            .sum();
+        sum as f32 * VOLUME
+    }
+
+    fn audio_channels(&self) -> &'static [&'static str] {
+        &CHANNEL_NAMES
+    }
+
+    fn set_audio_channels(&mut self, enabled: &[bool]) {
+        for (c, &e) in self.channels.iter_mut().zip(enabled) {
+            *c = e;
+        }
+    }
+
+    fn poll_irq(&mut self) -> bool {
+        self.irq.pending()
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        self.prg_banks.iter().for_each(|&b| w.usize(b));
+        self.chr_banks.iter().for_each(|&b| w.usize(b));
+        w.bool(self.ram_enabled);
+        w.bool(self.silenced);
+        self.irq.save_state(w);
+        self.opll.save_state(w);
+        self.mirroring.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        for b in self.prg_banks.iter_mut() {
+            *b = r.usize()? % self.prg_max;
+        }
+        for b in self.chr_banks.iter_mut() {
+            *b = r.usize()? & 0xff;
+        }
+        self.ram_enabled = r.bool()?;
+        self.silenced = r.bool()?;
+        self.irq.load_state(r)?;
+        self.opll.load_state(r)?;
+        self.mirroring.load_state(r)
+    }
+}
@@ -0,0 +1,627 @@
+//! the YM2413 (OPLL) derived FM synthesizer of VRC7
+//!
+//! 6 channels of 2 operators, a modulator feeding the phase of a carrier.
+//! like the real chip it works in the log domain, attenuations add up in
+//! 0.375 dB steps and a log-sin and an exp table turn them into samples.
+
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+/// cpu cycles per sample, the chip runs at 3.58 MHz / 72
+pub const SAMPLE_CYCLES: usize = 36;
+
+pub const CHANNELS: usize = 6;
+
+/// the built-in instruments 1-15, 0 is the custom one in $00-$07
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xe8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0d, 0xd8, 0xf6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xfa, 0xb2, 0x20, 0x12],
+    [0x31, 0x61, 0x0c, 0x07, 0xa8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1e, 0x06, 0xe1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xa3, 0xe2, 0xf4, 0xf4],
+    [0x21, 0x61, 0x1d, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xa2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xb5, 0x01, 0x0f, 0x0f, 0xa8, 0xa5, 0x51, 0x02],
+    [0x17, 0xc1, 0x24, 0x07, 0xf8, 0xf8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xd3, 0x05, 0xc9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0c, 0x00, 0x94, 0xc0, 0x33, 0xf6],
+    [0x21, 0x72, 0x0d, 0x00, 0xc1, 0xd5, 0x56, 0x06],
+];
+
+/// frequency multipliers, doubled so 0 can be a half
+const MULT: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+/// key scale attenuation of block 7 by the top 4 bits of fnum, at 6 dB/octave
+const KSL: [u8; 16] = [
+    0, 24, 32, 37, 40, 43, 45, 47, 48, 50, 51, 52, 53, 54, 55, 56,
+];
+
+/// envelope increments, by the low 2 bits of the rate and a 3 bit counter
+const EG_INC: [[u8; 8]; 4] = [
+    [0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 1, 0, 1, 1, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 1],
+];
+
+/// vibrato steps, in units of fnum / 256
+const PM: [i32; 8] = [0, 1, 2, 1, 0, -1, -2, -1];
+
+/// samples per step of the tremolo triangle (0-13-0) and the vibrato table
+const AM_PERIOD: u32 = 512;
+const PM_PERIOD: u32 = 1024;
+
+const MAX_ATTENUATION: u32 = 127;
+
+lazy_static::lazy_static! {
+    /// -log2(sin) of a quarter wave, in 1/256 units
+    static ref LOG_SIN: [u16; 256] = {
+        let mut table = [0u16; 256];
+        table.iter_mut().enumerate().for_each(|(i, t)| {
+            let x = ((i as f64 + 0.5) * std::f64::consts::PI / 512.0).sin();
+            *t = (-x.log2() * 256.0).round() as u16;
+        });
+        table
+    };
+
+    /// 2^-x of the fraction of a log value, full scale is 4096
+    static ref EXP: [u16; 256] = {
+        let mut table = [0u16; 256];
+        table.iter_mut().enumerate().for_each(|(i, t)| {
+            *t = (2f64.powf(-(i as f64) / 256.0) * 4096.0).round() as u16;
+        });
+        table
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EgState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// the half of a patch that belongs to one operator
+struct OpPatch {
+    am: bool,
+    vib: bool,
+    /// holds at the sustain level while keyed on, otherwise keeps decaying
+    sustained: bool,
+    ksr: bool,
+    mult: u32,
+    ksl: u8,
+    /// the negative half of the sine is cut off
+    rectified: bool,
+    ar: u8,
+    dr: u8,
+    sl: u8,
+    rr: u8,
+}
+
+impl OpPatch {
+    /// `n` is 0 for the modulator and 1 for the carrier
+    fn new(patch: &[u8; 8], n: usize) -> Self {
+        Self {
+            am: patch[n].get_bit(7),
+            vib: patch[n].get_bit(6),
+            sustained: patch[n].get_bit(5),
+            ksr: patch[n].get_bit(4),
+            mult: MULT[patch[n].get_bits(0..4) as usize],
+            ksl: patch[2 + n].get_bits(6..8),
+            rectified: patch[3].get_bit(3 + n),
+            ar: patch[4 + n].get_bits(4..8),
+            dr: patch[4 + n].get_bits(0..4),
+            sl: patch[6 + n].get_bits(4..8),
+            rr: patch[6 + n].get_bits(0..4),
+        }
+    }
+}
+
+struct Operator {
+    /// 19 bits, the top 10 index the sine
+    phase: u32,
+    /// attenuation in 0.375 dB steps
+    env: u8,
+    state: EgState,
+    output: i32,
+}
+
+impl Operator {
+    fn new() -> Self {
+        Self {
+            phase: 0,
+            env: MAX_ATTENUATION as u8,
+            state: EgState::Release,
+            output: 0,
+        }
+    }
+
+    fn key_on(&mut self) {
+        self.phase = 0;
+        self.state = EgState::Attack;
+    }
+
+    fn key_off(&mut self) {
+        self.state = EgState::Release;
+    }
+
+    fn tick_phase(&mut self, p: &OpPatch, fnum: u32, block: u8, pm: i32) {
+        let fnum = if p.vib {
+            (fnum as i32 + (((fnum >> 7) as i32 * pm) >> 1)) as u32
+        } else {
+            fnum
+        };
+        let inc = ((fnum << block) * p.mult) >> 1;
+        self.phase = (self.phase + inc) & 0x7ffff;
+    }
+
+    /// `release` is the rate after key off, `rks` the key scaling of the rates
+    fn tick_envelope(&mut self, p: &OpPatch, release: u8, rks: u8, counter: u32) {
+        let rate = |r: u8| if r == 0 { 0 } else { (r * 4 + rks).min(63) };
+        match self.state {
+            EgState::Attack => {
+                let rate = rate(p.ar);
+                if rate >= 60 {
+                    self.env = 0;
+                } else {
+                    let inc = eg_increment(rate, counter) as u32;
+                    if inc > 0 {
+                        let step = ((self.env as u32 + 1) * inc) >> 3;
+                        self.env -= step.clamp(1, self.env as u32) as u8;
+                    }
+                }
+                if self.env == 0 {
+                    self.state = EgState::Decay;
+                }
+            }
+            EgState::Decay => {
+                self.decay(rate(p.dr), counter);
+                if self.env as u32 >= p.sl as u32 * 8 {
+                    self.state = EgState::Sustain;
+                }
+            }
+            EgState::Sustain => {
+                if !p.sustained {
+                    self.decay(rate(p.rr), counter);
+                }
+            }
+            EgState::Release => self.decay(rate(release), counter),
+        }
+    }
+
+    fn decay(&mut self, rate: u8, counter: u32) {
+        let env = self.env as u32 + eg_increment(rate, counter) as u32;
+        self.env = env.min(MAX_ATTENUATION) as u8;
+    }
+
+    /// `index` is the 10 bit phase, `att` the attenuation in envelope steps
+    fn wave(index: i32, rectified: bool, att: u32) -> i32 {
+        let index = index as u32 & 0x3ff;
+        let negative = index.get_bit(9);
+        if negative && rectified {
+            return 0;
+        }
+
+        let quarter = if index.get_bit(8) {
+            0xff - (index & 0xff)
+        } else {
+            index & 0xff
+        };
+        let level = LOG_SIN[quarter as usize] as u32 + (att.min(MAX_ATTENUATION) << 4);
+        let amp = match level >> 8 {
+            shift @ 0..=15 => (EXP[(level & 0xff) as usize] >> shift) as i32,
+            _ => 0,
+        };
+        if negative {
+            -amp
+        } else {
+            amp
+        }
+    }
+}
+
+fn eg_increment(rate: u8, counter: u32) -> u8 {
+    if rate < 4 {
+        return 0;
+    }
+
+    let shift = 13u32.saturating_sub(rate as u32 >> 2);
+    if counter & ((1 << shift) - 1) != 0 {
+        return 0;
+    }
+    let inc = EG_INC[(rate & 3) as usize][(counter >> shift) as usize & 7];
+    inc << (rate >> 2).saturating_sub(12)
+}
+
+struct Channel {
+    fnum: u16,
+    block: u8,
+    key: bool,
+    sustain: bool,
+    instrument: u8,
+    volume: u8,
+    /// modulator and carrier
+    ops: [Operator; 2],
+    /// the last two modulator outputs, for the feedback
+    feedback: [i32; 2],
+}
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            fnum: 0,
+            block: 0,
+            key: false,
+            sustain: false,
+            instrument: 0,
+            volume: 0,
+            ops: [Operator::new(), Operator::new()],
+            feedback: [0; 2],
+        }
+    }
+
+    fn set_key(&mut self, key: bool) {
+        if key && !self.key {
+            self.ops.iter_mut().for_each(Operator::key_on);
+        } else if !key && self.key {
+            self.ops.iter_mut().for_each(Operator::key_off);
+        }
+        self.key = key;
+    }
+
+    /// run both operators for one sample, returns the carrier output
+    fn clock(&mut self, patch: &[u8; 8], lfo: (u32, i32), counter: u32) -> i32 {
+        let (am, pm) = lfo;
+        let fnum = self.fnum as u32;
+        let kcode = (self.block << 1) | (self.fnum >> 8) as u8;
+        let ksl_base =
+            (KSL[(self.fnum >> 5) as usize] as i32 - 16 * (7 - self.block as i32)).max(0);
+        let fb = patch[3].get_bits(0..3);
+        let mod_tl = patch[2].get_bits(0..6) as u32 * 2;
+        let car_tl = self.volume as u32 * 8;
+
+        let mut modulation = 0;
+        for (n, tl) in [(0, mod_tl), (1, car_tl)] {
+            let p = OpPatch::new(patch, n);
+            let rks = if p.ksr { kcode } else { kcode >> 2 };
+            let release = if self.sustain {
+                5
+            } else if !p.sustained {
+                7
+            } else {
+                p.rr
+            };
+
+            let op = &mut self.ops[n];
+            op.tick_envelope(&p, release, rks, counter);
+            op.tick_phase(&p, fnum, self.block, pm);
+
+            let ksl = match p.ksl {
+                0 => 0,
+                k => ksl_base as u32 >> (3 - k),
+            };
+            let att = op.env as u32 + tl + ksl + if p.am { am } else { 0 };
+            let index = (op.phase >> 9) as i32;
+
+            if n == 0 {
+                let fb = match fb {
+                    0 => 0,
+                    fb => (self.feedback[0] + self.feedback[1]) >> (9 - fb),
+                };
+                op.output = Operator::wave(index + fb, p.rectified, att);
+                self.feedback = [self.feedback[1], op.output];
+                modulation = op.output;
+            } else {
+                op.output = Operator::wave(index + modulation, p.rectified, att);
+            }
+        }
+        self.ops[1].output
+    }
+}
+
+pub struct Opll {
+    address: u8,
+    custom: [u8; 8],
+    channels: [Channel; CHANNELS],
+
+    /// sample counter for the envelopes and lfos
+    counter: u32,
+    cycles: usize,
+    /// one sample per channel, held between the samples of the chip
+    outputs: [i32; CHANNELS],
+}
+
+impl Opll {
+    pub fn new() -> Self {
+        Self {
+            address: 0,
+            custom: [0; 8],
+            channels: std::array::from_fn(|_| Channel::new()),
+
+            counter: 0,
+            cycles: 0,
+            outputs: [0; CHANNELS],
+        }
+    }
+
+    /// $9010
+    pub fn write_address(&mut self, data: u8) {
+        self.address = data;
+    }
+
+    /// $9030
+    pub fn write_data(&mut self, data: u8) {
+        let reg = self.address;
+        let n = (reg & 0x0f) as usize;
+        match reg {
+            0x00..=0x07 => self.custom[n] = data,
+            0x10..=0x15 => {
+                self.channels[n].fnum.set_bits(0..8, data as u16);
+            }
+            0x20..=0x25 => {
+                let ch = &mut self.channels[n];
+                ch.fnum.set_bit(8, data.get_bit(0));
+                ch.block = data.get_bits(1..4);
+                ch.sustain = data.get_bit(5);
+                ch.set_key(data.get_bit(4));
+            }
+            0x30..=0x35 => {
+                let ch = &mut self.channels[n];
+                ch.instrument = data.get_bits(4..8);
+                ch.volume = data.get_bits(0..4);
+            }
+            _ => {}
+        }
+    }
+
+    /// once per cpu cycle
+    pub fn tick(&mut self) {
+        self.cycles += 1;
+        if self.cycles == SAMPLE_CYCLES {
+            self.cycles = 0;
+            self.clock();
+        }
+    }
+
+    /// produce the next sample
+    fn clock(&mut self) {
+        self.counter = self.counter.wrapping_add(1);
+
+        let am_step = (self.counter / AM_PERIOD) % 26;
+        let am = if am_step <= 13 { am_step } else { 26 - am_step };
+        let pm = PM[((self.counter / PM_PERIOD) % 8) as usize];
+
+        for (ch, out) in self.channels.iter_mut().zip(self.outputs.iter_mut()) {
+            let patch = match ch.instrument {
+                0 => &self.custom,
+                n => &PATCHES[n as usize - 1],
+            };
+            *out = ch.clock(patch, (am, pm), self.counter);
+        }
+    }
+
+    /// the last sample of each channel, about +-4096 at full volume
+    pub fn outputs(&self) -> &[i32; CHANNELS] {
+        &self.outputs
+    }
+}
+
+impl Snapshot for Opll {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.address);
+        w.bytes(&self.custom);
+        for ch in self.channels.iter() {
+            w.u16(ch.fnum);
+            w.u8(ch.block);
+            w.bool(ch.key);
+            w.bool(ch.sustain);
+            w.u8(ch.instrument);
+            w.u8(ch.volume);
+            for op in ch.ops.iter() {
+                w.usize(op.phase as usize);
+                w.u8(op.env);
+                w.u8(op.state as u8);
+                w.u16(op.output as u16);
+            }
+            for &f in ch.feedback.iter() {
+                w.u16(f as u16);
+            }
+        }
+        w.usize(self.counter as usize);
+        w.usize(self.cycles);
+        for &o in self.outputs.iter() {
+            w.u16(o as u16);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.address = r.u8()?;
+        r.bytes(&mut self.custom)?;
+        for ch in self.channels.iter_mut() {
+            ch.fnum = r.u16()? & 0x1ff;
+            ch.block = r.u8()? & 0b111;
+            ch.key = r.bool()?;
+            ch.sustain = r.bool()?;
+            ch.instrument = r.u8()? & 0x0f;
+            ch.volume = r.u8()? & 0x0f;
+            for op in ch.ops.iter_mut() {
+                op.phase = r.usize()? as u32 & 0x7ffff;
+                op.env = (r.u8()? as u32).min(MAX_ATTENUATION) as u8;
+                op.state = match r.u8()? {
+                    0 => EgState::Attack,
+                    1 => EgState::Decay,
+                    2 => EgState::Sustain,
+                    _ => EgState::Release,
+                };
+                op.output = r.u16()? as i16 as i32;
+            }
+            for f in ch.feedback.iter_mut() {
+                *f = r.u16()? as i16 as i32;
+            }
+        }
+        self.counter = r.usize()? as u32;
+        self.cycles = r.usize()? % SAMPLE_CYCLES;
+        for o in self.outputs.iter_mut() {
+            *o = r.u16()? as i16 as i32;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// key on channel 0 with `instrument` at full volume
+    fn play(opll: &mut Opll, instrument: u8, fnum: u16, block: u8) {
+        let writes = [
+            (0x30, instrument << 4),
+            (0x10, fnum as u8),
+            (0x20, 0x10 | block << 1 | (fnum >> 8) as u8),
+        ];
+        for (reg, data) in writes {
+            opll.write_address(reg);
+            opll.write_data(data);
+        }
+    }
+
+    fn render(opll: &mut Opll, samples: usize) -> Vec<i16> {
+        (0..samples)
+            .map(|_| {
+                opll.clock();
+                opll.outputs()[0] as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pitch() {
+        // a plain sine carrier, the modulator fully attenuated
+        let mut opll = Opll::new();
+        let custom = [0x21, 0x21, 0x3f, 0x00, 0xf0, 0xf0, 0x0f, 0x0f];
+        for (reg, data) in custom.into_iter().enumerate() {
+            opll.write_address(reg as u8);
+            opll.write_data(data);
+        }
+        // A4 is fnum 290 in block 4 at 49716 Hz
+        play(&mut opll, 0, 290, 4);
+
+        let rate = 3_579_545 / 72;
+        let wave = render(&mut opll, rate);
+        let rising = wave.windows(2).filter(|w| w[0] < 0 && w[1] >= 0).count();
+        assert!((438..=442).contains(&rising), "{} Hz", rising);
+        assert!(wave.iter().any(|&s| s > 3500));
+    }
+
+    /// the custom patch with a quiet modulator, like `test_pitch`, with the carrier's byte 5 and 7
+    fn sine(carrier_ad: u8, carrier_sr: u8) -> Opll {
+        let mut opll = Opll::new();
+        let custom = [0x21, 0x21, 0x3f, 0x00, 0xf0, carrier_ad, 0x0f, carrier_sr];
+        for (reg, data) in custom.into_iter().enumerate() {
+            opll.write_address(reg as u8);
+            opll.write_data(data);
+        }
+        opll
+    }
+
+    /// the peak of each 440 Hz period
+    fn peaks(wave: &[i16]) -> Vec<i16> {
+        wave.chunks(113)
+            .map(|c| c.iter().map(|s| s.abs()).max().unwrap())
+            .collect()
+    }
+
+    /// samples until the peak falls `db` below the first one
+    fn fall_time(opll: &mut Opll, db: f64) -> usize {
+        let wave = render(opll, 1 << 16);
+        let peaks = peaks(&wave);
+        let target = peaks[0] as f64 * 10f64.powf(-db / 20.0);
+        peaks.iter().position(|&p| (p as f64) < target).unwrap() * 113
+    }
+
+    /// amplitude of `freq` (in cycles per sample) in `wave`, through a hann window
+    fn magnitude(wave: &[i16], freq: f64) -> f64 {
+        let n = wave.len() as f64;
+        let (mut re, mut im) = (0.0, 0.0);
+        for (i, &s) in wave.iter().enumerate() {
+            let w = 0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / n).cos();
+            let phase = 2.0 * std::f64::consts::PI * freq * i as f64;
+            re += s as f64 * w * phase.cos();
+            im += s as f64 * w * phase.sin();
+        }
+        (re * re + im * im).sqrt() / n
+    }
+
+    /// the datasheet steps: 3 dB per sustain level and per volume step
+    #[test]
+    fn test_levels() {
+        for (sl, volume) in [(4, 0), (0, 4), (2, 2)] {
+            let mut opll = sine(0xfc, sl << 4);
+            play(&mut opll, 0, 290, 4);
+            opll.write_address(0x30);
+            opll.write_data(volume);
+            let peaks = peaks(&render(&mut opll, 8192));
+            let db = -20.0 * (*peaks.last().unwrap() as f64 / 4096.0).log10();
+            assert!(
+                (db - 12.0).abs() < 0.5,
+                "sl {} volume {}: {} dB",
+                sl,
+                volume,
+                db
+            );
+        }
+    }
+
+    /// the decay is linear in dB and each step of the rate halves its time
+    #[test]
+    fn test_envelope_timing() {
+        let mut times = vec![];
+        for dr in 6..10 {
+            // sustain level 15 and not sustained, the decay runs to the bottom
+            let mut opll = sine(0xf0 | dr, 0xf0);
+            play(&mut opll, 0, 290, 4);
+            let t6 = fall_time(&mut opll, 6.0);
+            let mut opll = sine(0xf0 | dr, 0xf0);
+            play(&mut opll, 0, 290, 4);
+            let t24 = fall_time(&mut opll, 24.0);
+            let ratio = t24 as f64 / t6 as f64;
+            assert!((3.5..=4.5).contains(&ratio), "dr {}: {} / {}", dr, t24, t6);
+            times.push(t24);
+        }
+        for t in times.windows(2) {
+            let ratio = t[0] as f64 / t[1] as f64;
+            assert!((1.8..=2.2).contains(&ratio), "{:?}", times);
+        }
+    }
+
+    /// phase modulation by twice the carrier frequency only makes odd harmonics,
+    /// at the same frequency it makes all of them
+    #[test]
+    fn test_modulation_spectrum() {
+        for (mod_mult, even) in [(0x02, false), (0x01, true)] {
+            let mut opll = Opll::new();
+            // modulator at 15 dB, no feedback, both never decaying
+            let custom = [0x20 | mod_mult, 0x21, 0x14, 0x00, 0xf0, 0xf0, 0x0f, 0x0f];
+            for (reg, data) in custom.into_iter().enumerate() {
+                opll.write_address(reg as u8);
+                opll.write_data(data);
+            }
+            play(&mut opll, 0, 290, 4);
+            let wave = render(&mut opll, 1 << 14);
+            let wave = &wave[1 << 13..];
+
+            let f0 = (290 << 4) as f64 / (1 << 19) as f64;
+            let odd = magnitude(wave, f0) + magnitude(wave, 3.0 * f0);
+            let evens = magnitude(wave, 2.0 * f0) + magnitude(wave, 4.0 * f0);
+            if even {
+                assert!(evens > odd * 0.1, "{} {}", evens, odd);
+            } else {
+                assert!(evens < odd * 0.01, "{} {}", evens, odd);
+            }
+        }
+    }
+}
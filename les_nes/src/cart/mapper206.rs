@@ -0,0 +1,92 @@
+use super::Mirroring;
+use crate::state::{StateReader, StateResult, StateWriter};
+
+/// 206, Namco 108 and DxROM
+///
+/// 0x8000-0x9fff: 8 KB switchable PRG bank (R6),
+/// 0xa000-0xbfff: 8 KB switchable PRG bank (R7),
+/// 0xc000-0xffff: the last two 8 KB PRG banks,
+/// 2 * 2 KB CHR banks at 0x0000 (R0, R1) and 4 * 1 KB at 0x1000 (R2-R5).
+/// the MMC3 ancestor: only the bank select at $8000 and bank data at $8001,
+/// no mirroring control, prg modes or irq.
+pub struct Mapper206 {
+    command: u8,
+    /// R0-R7
+    banks: [usize; 8],
+    /// in 8 KB units
+    prg_max: usize,
+
+    mirroring: Mirroring,
+}
+
+impl Mapper206 {
+    pub fn new(mirroring: Mirroring, prg_banks: usize) -> Option<Self> {
+        if prg_banks == 0 {
+            return None;
+        }
+
+        Some(Self {
+            command: 0,
+            banks: [0; 8],
+            prg_max: prg_banks * 2,
+
+            mirroring,
+        })
+    }
+}
+
+impl super::Mapper for Mapper206 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let bank = match addr {
+            0x8000..=0x9fff => self.banks[6],
+            0xa000..=0xbfff => self.banks[7],
+            0xc000..=0xdfff => self.prg_max - 2,
+            0xe000..=0xffff => self.prg_max - 1,
+            _ => return None,
+        };
+        Some(bank * 0x2000 + (addr as usize & 0x1fff))
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+        match addr {
+            0x8000..=0x9fff if addr & 1 == 0 => self.command = data & 0b111,
+            0x8000..=0x9fff => {
+                let n = self.command as usize;
+                self.banks[n] = match n {
+                    0 | 1 => data as usize & 0x3e,
+                    2..=5 => data as usize & 0x3f,
+                    _ => (data as usize & 0x0f) % self.prg_max,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        let offset = match addr {
+            0x0000..=0x0fff => self.banks[addr as usize >> 11] * 0x400 + (addr as usize & 0x7ff),
+            _ => self.banks[2 + ((addr as usize - 0x1000) >> 10)] * 0x400 + (addr as usize & 0x3ff),
+        };
+        chr[offset % chr.len()]
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.command);
+        self.banks.iter().for_each(|&b| w.usize(b));
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.command = r.u8()? & 0b111;
+        for b in self.banks.iter_mut() {
+            *b = r.usize()? & 0x3f;
+        }
+        for b in &mut self.banks[6..] {
+            *b %= self.prg_max;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use super::Mirroring;
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+/// 225, 52-in-1, 64-in-1 and 72-in-1 multicarts
+///
+/// 0x8000-0xffff: one 32 KB or two mirrored 16 KB switchable PRG banks,
+/// 8 KB switchable CHR banks,
+/// the register is the write address: `.HMO PPPP PPCC CCCC`,
+/// H is the high bit of both banks, O picks 16 KB mode.
+/// 4 nibbles of ram at $5800-$5FFF.
+pub struct Mapper225 {
+    /// in 16 KB units
+    prg_bank: usize,
+    prg_banks: usize,
+    prg_16k: bool,
+    chr_bank: usize,
+    chr_banks: usize,
+    ram: [u8; 4],
+
+    mirroring: Mirroring,
+}
+
+impl Mapper225 {
+    pub fn new(mirroring: Mirroring, prg_banks: usize, chr_banks: usize) -> Option<Self> {
+        if !(prg_banks >= 2 && prg_banks.is_multiple_of(2) && chr_banks >= 1) {
+            return None;
+        }
+
+        Some(Self {
+            prg_bank: 0,
+            prg_banks,
+            prg_16k: false,
+            chr_bank: 0,
+            chr_banks,
+            ram: [0; 4],
+
+            mirroring,
+        })
+    }
+}
+
+impl super::Mapper for Mapper225 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let bank = if self.prg_16k {
+            self.prg_bank
+        } else {
+            self.prg_bank & !1 | (addr as usize >> 14 & 1)
+        };
+        Some(bank * 0x4000 + (addr as usize & 0x3fff))
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, _data: u8) {
+        let high = (addr.get_bit(14) as usize) << 6;
+        self.prg_bank = (high | addr.get_bits(6..12) as usize) % self.prg_banks;
+        self.prg_16k = addr.get_bit(12);
+        self.chr_bank = (high | addr.get_bits(0..6) as usize) % self.chr_banks;
+        self.mirroring = if addr.get_bit(13) {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        };
+    }
+
+    fn peek_low(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x5800..=0x5fff => Some(self.ram[addr as usize & 3]),
+            _ => None,
+        }
+    }
+
+    fn read_low(&mut self, addr: u16) -> Option<u8> {
+        self.peek_low(addr)
+    }
+
+    fn write_low(&mut self, addr: u16, data: u8) {
+        if let 0x5800..=0x5fff = addr {
+            self.ram[addr as usize & 3] = data & 0x0f;
+        }
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[self.chr_bank * 0x2000 + addr as usize]
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.usize(self.prg_bank);
+        w.bool(self.prg_16k);
+        w.usize(self.chr_bank);
+        w.bytes(&self.ram);
+        self.mirroring.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.prg_bank = r.usize()? % self.prg_banks;
+        self.prg_16k = r.bool()?;
+        self.chr_bank = r.usize()? % self.chr_banks;
+        r.bytes(&mut self.ram)?;
+        self.mirroring.load_state(r)
+    }
+}
@@ -0,0 +1,70 @@
+use super::Mirroring;
+use crate::state::{StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+/// 226, 76-in-1 and other 0x-in-1 multicarts
+///
+/// 0x8000-0xffff: one 32 KB or two mirrored 16 KB switchable PRG banks,
+/// 8 KB CHR RAM,
+/// 0x8000 (even addresses): `QMOP PPPP`, Q is prg bit 5, O picks 16 KB mode, M is vertical
+/// 0x8001 (odd addresses): `.... ...H`, prg bit 6
+pub struct Mapper226 {
+    regs: [u8; 2],
+    /// in 16 KB units
+    prg_banks: usize,
+}
+
+impl Mapper226 {
+    pub fn new(prg_banks: usize) -> Option<Self> {
+        if !(prg_banks >= 2 && prg_banks.is_multiple_of(2)) {
+            return None;
+        }
+
+        Some(Self {
+            regs: [0; 2],
+            prg_banks,
+        })
+    }
+
+    fn prg_bank(&self) -> usize {
+        let bank = self.regs[0].get_bits(0..5) as usize
+            | (self.regs[0].get_bit(7) as usize) << 5
+            | (self.regs[1].get_bit(0) as usize) << 6;
+        bank % self.prg_banks
+    }
+}
+
+impl super::Mapper for Mapper226 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let bank = if self.regs[0].get_bit(5) {
+            self.prg_bank()
+        } else {
+            self.prg_bank() & !1 | (addr as usize >> 14 & 1)
+        };
+        Some(bank * 0x4000 + (addr as usize & 0x3fff))
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, data: u8) {
+        self.regs[addr as usize & 1] = data;
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[addr as usize]
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.regs[0].get_bit(6) {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.regs);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        r.bytes(&mut self.regs)
+    }
+}
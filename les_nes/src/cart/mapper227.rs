@@ -0,0 +1,80 @@
+use super::Mirroring;
+use crate::state::{StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+/// 227, 1200-in-1 and other 0x-in-1 multicarts
+///
+/// 0x8000-0xbfff: 16 KB switchable PRG bank,
+/// 0xc000-0xffff: the same bank, its pair, or the first or last bank of its 128 KB block,
+/// 8 KB CHR RAM,
+/// the register is the write address: `.... ..LQ OPPP PPMS`,
+/// Q is prg bit 5, S picks 32 KB mode, M is horizontal,
+/// O is NROM mode, without it $C000 is fixed to the block's first bank, or last with L.
+pub struct Mapper227 {
+    latch: u16,
+    /// in 16 KB units
+    prg_banks: usize,
+}
+
+impl Mapper227 {
+    pub fn new(prg_banks: usize) -> Option<Self> {
+        if !(prg_banks >= 2 && prg_banks.is_multiple_of(2)) {
+            return None;
+        }
+
+        Some(Self {
+            latch: 0,
+            prg_banks,
+        })
+    }
+
+    fn prg_banks(&self) -> [usize; 2] {
+        let bank = self.latch.get_bits(2..7) as usize | (self.latch.get_bit(8) as usize) << 5;
+        let size_32k = self.latch.get_bit(0);
+        let first = if size_32k { bank & !1 } else { bank };
+        let second = if self.latch.get_bit(7) {
+            if size_32k {
+                bank | 1
+            } else {
+                bank
+            }
+        } else if self.latch.get_bit(9) {
+            bank | 7
+        } else {
+            bank & !7
+        };
+        [first % self.prg_banks, second % self.prg_banks]
+    }
+}
+
+impl super::Mapper for Mapper227 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let bank = self.prg_banks()[addr as usize >> 14 & 1];
+        Some(bank * 0x4000 + (addr as usize & 0x3fff))
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, _data: u8) {
+        self.latch = addr & 0x3ff;
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[addr as usize]
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.latch.get_bit(1) {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.latch);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.latch = r.u16()? & 0x3ff;
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use super::Mirroring;
+use crate::state::{StateReader, StateResult, StateWriter};
+use bit_field::BitField;
+
+/// 231, 20-in-1 multicart
+///
+/// 0x8000-0xbfff: 16 KB switchable PRG bank (always even),
+/// 0xc000-0xffff: the same bank, or the odd one after it,
+/// 8 KB CHR RAM,
+/// the register is the write address: `.... .... M.LP PPP.`, M is horizontal, L the odd bank.
+pub struct Mapper231 {
+    latch: u16,
+    /// in 16 KB units
+    prg_banks: usize,
+}
+
+impl Mapper231 {
+    pub fn new(prg_banks: usize) -> Option<Self> {
+        if !(prg_banks >= 2 && prg_banks.is_multiple_of(2)) {
+            return None;
+        }
+
+        Some(Self {
+            latch: 0,
+            prg_banks,
+        })
+    }
+}
+
+impl super::Mapper for Mapper231 {
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        let mut bank = self.latch as usize & 0x1e;
+        if addr >= 0xc000 {
+            bank |= self.latch.get_bit(5) as usize;
+        }
+        Some(bank % self.prg_banks * 0x4000 + (addr as usize & 0x3fff))
+    }
+
+    fn write_prg(&mut self, _prg: &mut [u8], addr: u16, _data: u8) {
+        self.latch = addr & 0xff;
+    }
+
+    fn read_chr(&self, chr: &[u8], addr: u16) -> u8 {
+        chr[addr as usize]
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.latch.get_bit(7) {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.latch);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.latch = r.u16()? & 0xff;
+        Ok(())
+    }
+}
@@ -1,8 +1,15 @@
+use std::io::{self, Write};
+
 use crate::bus::Bus;
-use addressing::{AddrMode, ADDR_MODES};
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
+use bit_field::BitField;
 use status::Status;
 
+pub use addressing::AddrMode;
+pub use disasm::{disassemble, disassemble_around, Instruction};
+
 mod addressing;
+mod disasm;
 mod op_code;
 mod status;
 
@@ -78,79 +85,26 @@ impl Cpu {
         self.pc = addr;
     }
 
-    pub fn dump(&self, bus: &mut Bus) {
-        use op_code::OP_NAMES;
-
-        print!("{:04X}  ", self.pc);
-
-        let mut pc = self.pc;
-        let op = bus.inspect(pc) as usize;
-        let name = OP_NAMES[op];
-        pc += 1;
-
-        match ADDR_MODES[op] {
-            AddrMode::IMP | AddrMode::ACC => {
-                print!("{:02X}        {}", op, name);
-            }
-            AddrMode::IMM => {
-                let op1 = bus.inspect(pc);
-                print!("{:02X} {:02X}     {}", op, op1, name);
-            }
-            AddrMode::ZEP => {
-                let op1 = bus.inspect(pc);
-                print!("{:02X} {:02X}     {}", op, op1, name);
-            }
-            AddrMode::ZPX => {
-                let op1 = bus.inspect(pc);
-                print!("{:02X} {:02X}     {}", op, op1, name);
-            }
-            AddrMode::ZPY => {
-                let op1 = bus.inspect(pc);
-                print!("{:02X} {:02X}     {}", op, op1, name);
-            }
-            AddrMode::IZX => {
-                let op1 = bus.inspect(pc);
-                print!("{:02X} {:02X}     {}", op, op1, name);
-            }
-            AddrMode::IZY => {
-                let op1 = bus.inspect(pc);
-                print!("{:02X} {:02X}     {}", op, op1, name);
-            }
-            AddrMode::ABS => {
-                let lb = bus.inspect(pc) as u16;
-                let hb = bus.inspect(pc.wrapping_add(1)) as u16;
-                print!("{:02X} {:02X} {:02X}  {}", op, lb, hb, name);
-            }
-            AddrMode::ABX => {
-                let lb = bus.inspect(pc) as u16;
-                let hb = bus.inspect(pc.wrapping_add(1)) as u16;
-                print!("{:02X} {:02X} {:02X}  {}", op, lb, hb, name);
-            }
-            AddrMode::ABY => {
-                let lb = bus.inspect(pc) as u16;
-                let hb = bus.inspect(pc.wrapping_add(1)) as u16;
-                print!("{:02X} {:02X} {:02X}  {}", op, lb, hb, name);
-            }
-            AddrMode::IND => {
-                let lb = bus.inspect(pc) as u16;
-                let hb = bus.inspect(pc.wrapping_add(1)) as u16;
-                print!("{:02X} {:02X} {:02X}  {}", op, lb, hb, name);
-            }
-            AddrMode::REL => {
-                let op1 = bus.inspect(pc);
-                print!("{:02X} {:02X}     {}", op, op1, name);
-            }
-        }
-
-        println!(
-            " A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+    /// write the next instruction and the registers in a nestest like format, e.g.
+    /// `C000  4C F5 C5  JMP $C5F5    A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7`
+    pub fn dump(&self, bus: &Bus, out: &mut impl Write) -> io::Result<()> {
+        let status = self.status();
+        let ins = Instruction::decode(bus, self.pc, Some(&status));
+        let (line, dot) = bus.ppu().timing();
+
+        writeln!(
+            out,
+            "{:<30} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:3},{:3} CYC:{}",
+            ins.to_string(),
             self.a,
             self.x,
             self.y,
             self.p.to_u8(),
             self.sp,
+            line,
+            dot,
             bus.cycles()
-        );
+        )
     }
 }
 
@@ -195,6 +149,7 @@ impl Cpu {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct CpuStatus {
     pub a: u8,
     pub x: u8,
@@ -216,3 +171,27 @@ impl Cpu {
         }
     }
 }
+
+impl Snapshot for Cpu {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.a);
+        w.u8(self.x);
+        w.u8(self.y);
+        w.u16(self.pc);
+        w.u8(self.sp);
+        w.u8(self.p.to_u8());
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.a = r.u8()?;
+        self.x = r.u8()?;
+        self.y = r.u8()?;
+        self.pc = r.u16()?;
+        self.sp = r.u8()?;
+
+        let p = r.u8()?;
+        self.p = p.into();
+        self.p.b = p.get_bit(4);
+        Ok(())
+    }
+}
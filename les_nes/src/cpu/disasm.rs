@@ -0,0 +1,215 @@
+use std::fmt;
+
+use super::addressing::{AddrMode, ADDR_MODES};
+use super::op_code::OP_NAMES;
+use super::CpuStatus;
+use crate::Bus;
+
+/// a decoded instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub mnemonic: &'static str,
+    pub mode: AddrMode,
+    /// e.g. `#$10`, `($20),Y` or the target of a branch
+    pub operand: String,
+    /// the address the operand refers to, only known for indexed modes when decoded with registers
+    pub effective_addr: Option<u16>,
+    /// where a branch, jump or call goes
+    pub target: Option<u16>,
+}
+
+impl Instruction {
+    /// decode the instruction at `addr` without side effects
+    ///
+    /// `regs` should be the current registers when `addr` is the pc,
+    /// they are used to resolve indexed and indirect addresses.
+    pub fn decode(bus: &Bus, addr: u16, regs: Option<&CpuStatus>) -> Self {
+        let op = bus.peek(addr);
+        let mode = ADDR_MODES[op as usize];
+        let mnemonic = OP_NAMES[op as usize];
+
+        let len = match mode {
+            AddrMode::IMP | AddrMode::ACC => 1,
+            AddrMode::ABS | AddrMode::ABX | AddrMode::ABY | AddrMode::IND => 3,
+            _ => 2,
+        };
+        let bytes: Vec<u8> = (0..len).map(|i| bus.peek(addr.wrapping_add(i))).collect();
+
+        let b = bytes.get(1).copied().unwrap_or_default();
+        let w = u16::from_le_bytes([b, bytes.get(2).copied().unwrap_or_default()]);
+        let peek_word = |lo: u16, hi: u16| u16::from_le_bytes([bus.peek(lo), bus.peek(hi)]);
+
+        let (operand, effective_addr) = match mode {
+            AddrMode::IMP => (String::new(), None),
+            AddrMode::ACC => ("A".to_owned(), None),
+            AddrMode::IMM => (format!("#${:02X}", b), None),
+            AddrMode::ZEP => (format!("${:02X}", b), Some(b as u16)),
+            AddrMode::ZPX => (
+                format!("${:02X},X", b),
+                regs.map(|r| b.wrapping_add(r.x) as u16),
+            ),
+            AddrMode::ZPY => (
+                format!("${:02X},Y", b),
+                regs.map(|r| b.wrapping_add(r.y) as u16),
+            ),
+            AddrMode::IZX => (
+                format!("(${:02X},X)", b),
+                regs.map(|r| {
+                    let base = b.wrapping_add(r.x);
+                    peek_word(base as u16, base.wrapping_add(1) as u16)
+                }),
+            ),
+            AddrMode::IZY => (
+                format!("(${:02X}),Y", b),
+                regs.map(|r| {
+                    peek_word(b as u16, b.wrapping_add(1) as u16).wrapping_add(r.y as u16)
+                }),
+            ),
+            AddrMode::ABS => (format!("${:04X}", w), Some(w)),
+            AddrMode::ABX => (
+                format!("${:04X},X", w),
+                regs.map(|r| w.wrapping_add(r.x as u16)),
+            ),
+            AddrMode::ABY => (
+                format!("${:04X},Y", w),
+                regs.map(|r| w.wrapping_add(r.y as u16)),
+            ),
+            AddrMode::IND => (
+                format!("(${:04X})", w),
+                // the high byte doesn't cross the page
+                Some(peek_word(w, (w & 0xff00) | (w.wrapping_add(1) & 0x00ff))),
+            ),
+            AddrMode::REL => {
+                let target = addr.wrapping_add(2).wrapping_add(b as i8 as u16);
+                (format!("${:04X}", target), Some(target))
+            }
+        };
+
+        let target = match (mode, mnemonic) {
+            (AddrMode::REL, _) => effective_addr,
+            (AddrMode::ABS, "JMP" | "JSR") | (AddrMode::IND, _) => effective_addr,
+            _ => None,
+        };
+
+        Self {
+            addr,
+            bytes,
+            mnemonic,
+            mode,
+            operand,
+            effective_addr,
+            target,
+        }
+    }
+
+    pub fn size(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.size())
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// `C000  4C F5 C5  JMP $C5F5`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(
+            f,
+            "{:04X}  {:<8}  {} {}",
+            self.addr,
+            bytes.join(" "),
+            self.mnemonic,
+            self.operand
+        )
+    }
+}
+
+/// decode every instruction starting in `start..end`
+pub fn disassemble(bus: &Bus, start: u16, end: u16) -> Vec<Instruction> {
+    let mut ins = vec![];
+    let mut addr = start;
+    while addr < end {
+        let i = Instruction::decode(bus, addr, None);
+        addr = match addr.checked_add(i.size()) {
+            Some(next) => next,
+            None => end,
+        };
+        ins.push(i);
+    }
+    ins
+}
+
+/// a listing with `before` instructions ahead of `pc` and `after` following it
+///
+/// code can't be decoded backwards reliably, so this picks the first starting point
+/// whose decoding lines up with `pc`.
+pub fn disassemble_around(
+    bus: &Bus,
+    regs: &CpuStatus,
+    before: usize,
+    after: usize,
+) -> Vec<Instruction> {
+    let pc = regs.pc;
+
+    let mut ins = vec![];
+    let lookback = (before as u16).saturating_mul(3).min(pc);
+    for start in (pc - lookback)..pc {
+        let listing = disassemble(bus, start, pc);
+        if listing.last().is_some_and(|i| i.next_addr() == pc) {
+            ins = listing;
+            break;
+        }
+    }
+    ins.drain(..ins.len().saturating_sub(before));
+
+    let mut addr = pc;
+    for n in 0..=after {
+        let i = Instruction::decode(bus, addr, (n == 0).then_some(regs));
+        addr = i.next_addr();
+        ins.push(i);
+    }
+    ins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cartridge, Cpu};
+
+    #[test]
+    fn test_decode() {
+        let mut bus = Bus::new(Cartridge::empty());
+        let code = [0xa9, 0x10, 0xb1, 0x20, 0xd0, 0xfa, 0x6c, 0xff, 0x02];
+        for (i, &b) in code.iter().enumerate() {
+            bus.write(0x200 + i as u16, b);
+        }
+        bus.write(0x20, 0x00);
+        bus.write(0x21, 0x03);
+
+        let ins = disassemble(&bus, 0x200, 0x200 + code.len() as u16);
+        let text: Vec<String> = ins.iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            text,
+            [
+                "0200  A9 10     LDA #$10",
+                "0202  B1 20     LDA ($20),Y",
+                "0204  D0 FA     BNE $0200",
+                "0206  6C FF 02  JMP ($02FF)",
+            ]
+        );
+        assert_eq!(ins[2].target, Some(0x200));
+        assert_eq!(ins[1].effective_addr, None);
+
+        let mut regs = Cpu::default().status();
+        regs.pc = 0x202;
+        regs.y = 0x04;
+        let around = disassemble_around(&bus, &regs, 1, 1);
+        assert_eq!(around.len(), 3);
+        assert_eq!(around[0].addr, 0x200);
+        assert_eq!(around[1].effective_addr, Some(0x304));
+    }
+}
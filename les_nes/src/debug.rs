@@ -0,0 +1,375 @@
+//! breakpoints and watchpoints
+//!
+//! the bus calls into the debugger before each instruction and on every cpu read/write.
+//! once something hits, the `run_*` methods of the bus stop until `resume` is called.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::cpu::{CpuStatus, Interrupt};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddrSpace {
+    Cpu,
+    /// vram as seen through $2007, rendering fetches are not watched
+    Ppu,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Exec,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    Sp,
+    P,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// `<reg> <op> <value>`, e.g. `a == $10` or `x >= 3`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub reg: Register,
+    pub op: CmpOp,
+    pub value: u8,
+}
+
+impl Condition {
+    pub fn eval(&self, cpu: &CpuStatus) -> bool {
+        let v = match self.reg {
+            Register::A => cpu.a,
+            Register::X => cpu.x,
+            Register::Y => cpu.y,
+            Register::Sp => cpu.sp,
+            Register::P => cpu.p.to_u8(),
+        };
+
+        match self.op {
+            CmpOp::Eq => v == self.value,
+            CmpOp::Ne => v != self.value,
+            CmpOp::Lt => v < self.value,
+            CmpOp::Le => v <= self.value,
+            CmpOp::Gt => v > self.value,
+            CmpOp::Ge => v >= self.value,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        let (Some(reg), Some(op), Some(value), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(format!("expected `<reg> <op> <value>`, got `{}`", s));
+        };
+
+        let reg = match reg.to_ascii_lowercase().as_str() {
+            "a" => Register::A,
+            "x" => Register::X,
+            "y" => Register::Y,
+            "sp" => Register::Sp,
+            "p" => Register::P,
+            _ => return Err(format!("unknown register `{}`", reg)),
+        };
+        let op = match op {
+            "==" => CmpOp::Eq,
+            "!=" => CmpOp::Ne,
+            "<" => CmpOp::Lt,
+            "<=" => CmpOp::Le,
+            ">" => CmpOp::Gt,
+            ">=" => CmpOp::Ge,
+            _ => return Err(format!("unknown operator `{}`", op)),
+        };
+        let value = parse_u8(value).ok_or(format!("invalid value `{}`", value))?;
+
+        Ok(Self { reg, op, value })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reg = match self.reg {
+            Register::A => "a",
+            Register::X => "x",
+            Register::Y => "y",
+            Register::Sp => "sp",
+            Register::P => "p",
+        };
+        let op = match self.op {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        };
+        write!(f, "{} {} ${:02X}", reg, op, self.value)
+    }
+}
+
+/// decimal, or hex with a `$` or `0x` prefix
+fn parse_u8(s: &str) -> Option<u8> {
+    match s.strip_prefix('$').or_else(|| s.strip_prefix("0x")) {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// break before the instruction at `pc` runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub pc: u16,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub space: AddrSpace,
+    pub range: RangeInclusive<u16>,
+    pub read: bool,
+    pub write: bool,
+    /// only meaningful for the cpu address space
+    pub exec: bool,
+    pub enabled: bool,
+}
+
+impl Watchpoint {
+    fn hit(&self, space: AddrSpace, addr: u16, access: Access) -> bool {
+        self.enabled
+            && self.space == space
+            && self.range.contains(&addr)
+            && match access {
+                Access::Read => self.read,
+                Access::Write => self.write,
+                Access::Exec => self.exec,
+            }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakReason {
+    Breakpoint(u16),
+    Watchpoint {
+        space: AddrSpace,
+        addr: u16,
+        access: Access,
+        data: u8,
+    },
+    Nmi,
+    Irq,
+    /// step over, step out or run to cursor finished
+    Target(u16),
+}
+
+impl fmt::Display for BreakReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakReason::Breakpoint(pc) => write!(f, "breakpoint at ${:04X}", pc),
+            BreakReason::Watchpoint {
+                space,
+                addr,
+                access,
+                data,
+            } => write!(
+                f,
+                "{:?} ${:04X} ({:?}) = ${:02X}",
+                access, addr, space, data
+            ),
+            BreakReason::Nmi => write!(f, "nmi"),
+            BreakReason::Irq => write!(f, "irq"),
+            BreakReason::Target(pc) => write!(f, "stopped at ${:04X}", pc),
+        }
+    }
+}
+
+/// a temporary stop set by step over/out and run to cursor
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    /// reach `pc` with the stack at least as high as `sp`, so recursion doesn't stop early
+    Pc { pc: u16, sp: Option<u8> },
+    /// the stack unwinds above `sp`
+    Return { sp: u8 },
+}
+
+#[derive(Debug, Default)]
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub break_on_nmi: bool,
+    pub break_on_irq: bool,
+
+    target: Option<Target>,
+    halted: Option<BreakReason>,
+    /// the halt came from `on_exec`, before the instruction at the pc ran
+    halted_at_exec: bool,
+    /// let the instruction we stopped at run once after resuming
+    resuming: bool,
+}
+
+impl Debugger {
+    /// why emulation is stopped, if it is
+    pub fn halted(&self) -> Option<&BreakReason> {
+        self.halted.as_ref()
+    }
+
+    pub fn resume(&mut self) {
+        // watchpoint and interrupt stops come after their instruction ran, check the next one
+        if self.halted.take().is_some() {
+            self.resuming = std::mem::take(&mut self.halted_at_exec);
+        }
+    }
+
+    pub(crate) fn run_to(&mut self, pc: u16, sp: Option<u8>) {
+        self.target = Some(Target::Pc { pc, sp });
+        self.resume();
+    }
+
+    pub(crate) fn run_to_return(&mut self, sp: u8) {
+        self.target = Some(Target::Return { sp });
+        self.resume();
+    }
+
+    pub(crate) fn halt(&mut self, reason: BreakReason) {
+        if self.halted.is_none() {
+            self.halted = Some(reason);
+        }
+    }
+
+    /// true if the instruction at the pc shouldn't run
+    pub(crate) fn on_exec(&mut self, cpu: &CpuStatus) -> bool {
+        if std::mem::take(&mut self.resuming) {
+            return false;
+        }
+
+        let was_halted = self.halted.is_some();
+        let pc = cpu.pc;
+        let reached = match self.target {
+            Some(Target::Pc { pc: target, sp }) => pc == target && sp.is_none_or(|sp| cpu.sp >= sp),
+            Some(Target::Return { sp }) => cpu.sp > sp,
+            None => false,
+        };
+
+        if reached {
+            self.target = None;
+            self.halt(BreakReason::Target(pc));
+        } else if self
+            .breakpoints
+            .iter()
+            .any(|b| b.enabled && b.pc == pc && b.condition.is_none_or(|c| c.eval(cpu)))
+        {
+            self.halt(BreakReason::Breakpoint(pc));
+        } else {
+            self.on_access(AddrSpace::Cpu, pc, Access::Exec, 0);
+        }
+
+        if !was_halted && self.halted.is_some() {
+            self.halted_at_exec = true;
+        }
+        self.halted.is_some()
+    }
+
+    pub(crate) fn on_access(&mut self, space: AddrSpace, addr: u16, access: Access, data: u8) {
+        if self.watchpoints.iter().any(|w| w.hit(space, addr, access)) {
+            self.halt(BreakReason::Watchpoint {
+                space,
+                addr,
+                access,
+                data,
+            });
+        }
+    }
+
+    pub(crate) fn on_interrupt(&mut self, interrupt: Interrupt) {
+        match interrupt {
+            Interrupt::NMI if self.break_on_nmi => self.halt(BreakReason::Nmi),
+            Interrupt::IRQ if self.break_on_irq => self.halt(BreakReason::Irq),
+            _ => {}
+        }
+    }
+
+    /// skip the hooks entirely when there is nothing to check
+    pub(crate) fn watching(&self) -> bool {
+        !self.watchpoints.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition() {
+        let c: Condition = "a >= $10".parse().unwrap();
+        assert_eq!(
+            c,
+            Condition {
+                reg: Register::A,
+                op: CmpOp::Ge,
+                value: 0x10
+            }
+        );
+        assert_eq!(c.to_string().parse(), Ok(c));
+        assert!("q == 1".parse::<Condition>().is_err());
+        assert!("x == 256".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn test_resume() {
+        let mut dbg = Debugger {
+            breakpoints: vec![Breakpoint {
+                pc: 0x8003,
+                condition: None,
+                enabled: true,
+            }],
+            watchpoints: vec![Watchpoint {
+                space: AddrSpace::Cpu,
+                range: 0x0010..=0x0010,
+                read: false,
+                write: true,
+                exec: false,
+                enabled: true,
+            }],
+            ..Default::default()
+        };
+        let mut cpu = CpuStatus {
+            a: 0,
+            x: 0,
+            y: 0,
+            pc: 0x8000,
+            sp: 0xfd,
+            p: Default::default(),
+        };
+
+        // the write at $8000 stops, the breakpoint at the next instruction still hits
+        assert!(!dbg.on_exec(&cpu));
+        dbg.on_access(AddrSpace::Cpu, 0x0010, Access::Write, 1);
+        dbg.resume();
+        cpu.pc = 0x8003;
+        assert!(dbg.on_exec(&cpu));
+
+        // resuming from the breakpoint runs its instruction
+        dbg.resume();
+        assert!(!dbg.on_exec(&cpu));
+        assert!(dbg.on_exec(&cpu));
+    }
+}
@@ -4,13 +4,19 @@ pub mod apu;
 pub mod bus;
 pub mod cart;
 pub mod cpu;
+pub mod debug;
 pub mod ppu;
+pub mod state;
+pub mod trace;
 
 pub use apu::{Apu, Resampler};
-pub use bus::{Bus, InputStates};
+pub use bus::{Bus, Frame, InputStates};
 pub use cart::Cartridge;
 pub use cpu::Cpu;
+pub use debug::Debugger;
 pub use ppu::Ppu;
+pub use state::StateError;
+pub use trace::{TraceFilter, Tracer};
 
 /// NES NTSC
 pub const MASTER_CLOCK: f32 = 2147_7272.0;
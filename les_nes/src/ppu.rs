@@ -1,5 +1,6 @@
 use self::regs::*;
 use crate::cart::Cartridge;
+use crate::state::{Snapshot, StateError, StateReader, StateResult, StateWriter};
 use bit_field::BitField;
 use std::ops::IndexMut;
 
@@ -97,13 +98,6 @@ impl Ppu {
     pub fn tick(&mut self, cart: &mut Cartridge) {
         self.update(cart);
 
-        if (self.mask.show_bg() || self.mask.show_sp())
-            && self.dot == 324
-            && (self.line < 240 || self.line == 261)
-        {
-            cart.update_scanline();
-        }
-
         if self.line == 241 && self.dot == 1 {
             self.status.set_vblank(true);
 
@@ -138,7 +132,7 @@ impl Ppu {
         }
     }
 
-    fn update(&mut self, cart: &Cartridge) {
+    fn update(&mut self, cart: &mut Cartridge) {
         // on visible scanlines, output pixels
         if (0..240).contains(&self.line) && (1..257).contains(&self.dot) {
             let (mut bg_tile, mut bg_pal_index) = (0, 0);
@@ -202,26 +196,23 @@ impl Ppu {
             }
         }
 
-        // on visible scanlines and pre-render scanline, fetch data
-        if (0..240).contains(&self.line) || self.line == 261 {
-            if self.mask.show_bg() {
-                self.update_bg(cart);
-            }
-
-            if self.mask.show_sp() {
-                self.update_sp(cart);
-            }
+        // on visible scanlines and pre-render scanline, fetch data.
+        // the ppu does all fetches while either layer is on, mappers watch them
+        let rendering = self.mask.show_bg() || self.mask.show_sp();
+        if rendering && ((0..240).contains(&self.line) || self.line == 261) {
+            self.update_bg(cart);
+            self.update_sp(cart);
         }
     }
 
-    fn update_bg(&mut self, cart: &Cartridge) {
+    fn update_bg(&mut self, cart: &mut Cartridge) {
         if (1..257).contains(&self.dot) || (321..337).contains(&self.dot) {
             self.rs.attr_bits.shift();
             self.rs.tile_bits.shift();
 
             match (self.dot - 1) % 8 {
-                1 => self.rs.nm_byte = self.read_vram(cart, self.v.tile_addr()),
-                3 => self.rs.attr_byte = self.read_vram(cart, self.v.attr_addr()),
+                1 => self.rs.nm_byte = self.fetch_vram(cart, self.v.tile_addr()),
+                3 => self.rs.attr_byte = self.fetch_vram(cart, self.v.attr_addr()),
                 5 => {}
                 7 => {
                     let (b0, b1, a0, a1) = {
@@ -232,8 +223,8 @@ impl Ppu {
                             + self.v.y()
                             + self.rs.nm_byte as u16 * 0x10;
                         (
-                            self.read_vram(cart, chr_addr),
-                            self.read_vram(cart, chr_addr + 8),
+                            self.fetch_vram(cart, chr_addr),
+                            self.fetch_vram(cart, chr_addr + 8),
                             attr.get_bit(0) as u8 * 0xff,
                             attr.get_bit(1) as u8 * 0xff,
                         )
@@ -253,6 +244,15 @@ impl Ppu {
             }
         }
 
+        // the nametable fetches during sprite fetching and at the end of the line are unused,
+        // but mappers count them
+        if ((257..321).contains(&self.dot) && matches!((self.dot - 257) % 8, 1 | 3))
+            || self.dot == 337
+            || self.dot == 339
+        {
+            self.fetch_vram(cart, self.v.tile_addr());
+        }
+
         // update horizontal
         if self.dot == 257 {
             self.v.copy_vx(&self.t);
@@ -264,7 +264,7 @@ impl Ppu {
         }
     }
 
-    fn update_sp(&mut self, cart: &Cartridge) {
+    fn update_sp(&mut self, cart: &mut Cartridge) {
         // 1..=64 dots, clear secondary oam
         if self.dot == 64 && self.line != 261 {
             self.rs.sec_oam.fill(0xff);
@@ -298,44 +298,45 @@ impl Ppu {
             self.rs.sp_n += 1;
         }
 
-        // fetch sprite data
+        // fetch sprite data, empty slots fetch tile $FF like the hardware
         if (257..321).contains(&self.dot) && (self.dot - 257) % 8 == 7 {
             let sp_n = (self.dot - 257) / 8;
 
-            if sp_n < self.rs.sp_count {
-                let addr = sp_n * 4;
-                let sp_y = self.line as u16 - self.rs.sec_oam[addr + 0] as u16;
-                let index = self.rs.sec_oam[addr + 1] as u16;
-                let attr = self.rs.sec_oam[addr + 2];
-                let sp_x = self.rs.sec_oam[addr + 3];
-
-                self.rs.sprites[sp_n].x = sp_x;
-                self.rs.sprites[sp_n].is_sp_zero = sp_n == 0 && self.rs.sp_zero;
+            let addr = sp_n * 4;
+            let sp_y = (self.line as u16).wrapping_sub(self.rs.sec_oam[addr + 0] as u16);
+            let index = self.rs.sec_oam[addr + 1] as u16;
+            let attr = self.rs.sec_oam[addr + 2];
+            let sp_x = self.rs.sec_oam[addr + 3];
 
-                // 76543210
-                // ||||||||
-                // ||||||++- palette of sprite
-                // |||+++--- unimplemented
-                // ||+------ priority (0: in front of background; 1: behind background)
-                // |+------- flip sprite horizontally
-                // +-------- flip sprite vertically
+            // 76543210
+            // ||||||||
+            // ||||||++- palette of sprite
+            // |||+++--- unimplemented
+            // ||+------ priority (0: in front of background; 1: behind background)
+            // |+------- flip sprite horizontally
+            // +-------- flip sprite vertically
 
-                let (mut tile_b0, mut tile_b1, attr_b0, attr_b1) = {
-                    let tile_y = (sp_y & 0x07) ^ (attr.get_bit(7) as u16 * 0x07);
+            let (mut tile_b0, mut tile_b1, attr_b0, attr_b1) = {
+                let tile_y = (sp_y & 0x07) ^ (attr.get_bit(7) as u16 * 0x07);
 
-                    let tile_addr = if self.ctrl.sp_size() == 8 {
-                        self.ctrl.sp_pattern_table() + index * 0x10
-                    } else {
-                        let tile_offset = ((sp_y >= 8) ^ attr.get_bit(7)) as u16;
-                        ((index & 0b01) * 0x1000) + ((index & 0xfe) + tile_offset) * 0x10
-                    };
-                    (
-                        self.read_vram(cart, tile_addr + tile_y),
-                        self.read_vram(cart, tile_addr + tile_y + 8),
-                        attr.get_bit(0) as u8 * 0xff,
-                        attr.get_bit(1) as u8 * 0xff,
-                    )
+                let tile_addr = if self.ctrl.sp_size() == 8 {
+                    self.ctrl.sp_pattern_table() + index * 0x10
+                } else {
+                    let tile_offset = ((sp_y >= 8) ^ attr.get_bit(7)) as u16;
+                    ((index & 0b01) * 0x1000) + ((index & 0xfe) + tile_offset) * 0x10
                 };
+                (
+                    self.fetch_vram(cart, tile_addr + tile_y),
+                    self.fetch_vram(cart, tile_addr + tile_y + 8),
+                    attr.get_bit(0) as u8 * 0xff,
+                    attr.get_bit(1) as u8 * 0xff,
+                )
+            };
+
+            if sp_n < self.rs.sp_count {
+                self.rs.sprites[sp_n].x = sp_x;
+                self.rs.sprites[sp_n].is_sp_zero = sp_n == 0 && self.rs.sp_zero;
+
                 if attr.get_bit(6) {
                     tile_b0 = tile_b0.reverse_bits();
                     tile_b1 = tile_b1.reverse_bits();
@@ -374,13 +375,18 @@ impl Ppu {
         self.frames
     }
 
+    /// address the next $2007 access goes to
+    pub fn vram_addr(&self) -> u16 {
+        self.v.addr() & 0x3fff
+    }
+
     pub fn display_buf(&self) -> &[u8] {
         self.rs.back_buf.as_ref()
     }
 }
 
 impl Ppu {
-    pub fn read(&mut self, cart: &Cartridge, addr: u16) -> u8 {
+    pub fn read(&mut self, cart: &mut Cartridge, addr: u16) -> u8 {
         let addr = (addr - 0x2000) & 0x07;
         match addr {
             0x00 => 0x00,
@@ -399,8 +405,9 @@ impl Ppu {
                 let addr = self.v.addr();
                 let data = self.data_buf;
 
-                self.data_buf = self.read_vram(cart, addr);
+                self.data_buf = self.fetch_vram(cart, addr);
                 self.v.inc(self.ctrl.addr_inc());
+                cart.ppu_address(self.v.addr() & 0x3fff);
 
                 if addr < 0x3f00 {
                     data
@@ -446,11 +453,14 @@ impl Ppu {
                     self.t.set_bits(0x00..0x08, data as u16);
                     self.v = self.t.clone();
                     self.w = WriteLatch::Step0;
+                    cart.ppu_address(self.v.addr() & 0x3fff);
                 }
             }
             0x07 => {
+                cart.ppu_address(self.v.addr() & 0x3fff);
                 self.write_vram(cart, self.v.addr(), data);
                 self.v.inc(self.ctrl.addr_inc());
+                cart.ppu_address(self.v.addr() & 0x3fff);
             }
 
             _ => unreachable!(),
@@ -461,11 +471,24 @@ impl Ppu {
         self.oam[addr as usize] = data;
     }
 
+    /// a read on the ppu bus, which the mapper gets to see
+    fn fetch_vram(&self, cart: &mut Cartridge, addr: u16) -> u8 {
+        cart.ppu_address(addr & 0x3fff);
+        let data = self.read_vram(cart, addr);
+        cart.ppu_read(addr & 0x3fff);
+        data
+    }
+
     fn read_vram(&self, cart: &Cartridge, addr: u16) -> u8 {
         let addr = addr & 0x3fff;
         match addr {
-            0x0000..=0x1fff => cart.read_chr(addr),
-            0x2000..=0x3eff => self.nametables[cart.nm_addr(addr)],
+            0x0000..=0x1fff => match cart.chr_ciram_addr(addr) {
+                Some(i) => self.nametables[i],
+                None => cart.read_chr(addr),
+            },
+            0x2000..=0x3eff => cart
+                .read_nametable(addr)
+                .unwrap_or_else(|| self.nametables[cart.nm_addr(addr)]),
             0x3f00..=0x3fff => {
                 let pal_hack = !(self.mask.show_bg() || self.mask.show_sp())
                     && (0x3f00..=0x3fff).contains(&self.v.addr());
@@ -486,8 +509,15 @@ impl Ppu {
     fn write_vram(&mut self, cart: &mut Cartridge, addr: u16, data: u8) {
         let addr = addr & 0x3fff;
         match addr {
-            0x0000..=0x1fff => cart.write_chr(addr, data),
-            0x2000..=0x3eff => self.nametables[cart.nm_addr(addr)] = data,
+            0x0000..=0x1fff => match cart.chr_ciram_addr(addr) {
+                Some(i) => self.nametables[i] = data,
+                None => cart.write_chr(addr, data),
+            },
+            0x2000..=0x3eff => {
+                if !cart.write_nametable(addr, data) {
+                    self.nametables[cart.nm_addr(addr)] = data;
+                }
+            }
             0x3f00..=0x3fff => {
                 let data = data & 0x3f;
                 let addr = (addr & 0x1f) as usize;
@@ -524,6 +554,117 @@ impl Default for RenderState {
     }
 }
 
+impl Snapshot for SpriteState {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.x);
+        self.tile_bits.save_state(w);
+        self.attr_bits.save_state(w);
+        w.bool(self.is_sp_zero);
+        w.u8(self.priority);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.x = r.u8()?;
+        self.tile_bits.load_state(r)?;
+        self.attr_bits.load_state(r)?;
+        self.is_sp_zero = r.bool()?;
+        self.priority = r.u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for RenderState {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.nm_byte);
+        w.u8(self.attr_byte);
+        self.tile_bits.save_state(w);
+        self.attr_bits.save_state(w);
+
+        w.usize(self.sp_n);
+        w.usize(self.sp_count);
+        w.bytes(self.sec_oam.as_ref());
+        self.sprites.iter().for_each(|sp| sp.save_state(w));
+        w.bool(self.sp_zero);
+
+        w.bytes(self.buf.as_ref());
+        w.bytes(self.back_buf.as_ref());
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.nm_byte = r.u8()?;
+        self.attr_byte = r.u8()?;
+        self.tile_bits.load_state(r)?;
+        self.attr_bits.load_state(r)?;
+
+        self.sp_n = r.usize()?;
+        self.sp_count = r.usize()?;
+        if self.sp_count > 8 {
+            return Err(StateError::Invalid("sprite count"));
+        }
+        r.bytes(self.sec_oam.as_mut())?;
+        for sp in self.sprites.iter_mut() {
+            sp.load_state(r)?;
+        }
+        self.sp_zero = r.bool()?;
+
+        r.bytes(self.buf.as_mut())?;
+        r.bytes(self.back_buf.as_mut())
+    }
+}
+
+impl Snapshot for Ppu {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(self.nametables.as_ref());
+        w.bytes(self.palettes.as_ref());
+        w.bytes(self.oam.as_ref());
+
+        self.ctrl.save_state(w);
+        self.mask.save_state(w);
+        self.status.save_state(w);
+        w.u8(self.oam_addr as u8);
+        w.u8(self.data_buf);
+
+        self.v.save_state(w);
+        self.t.save_state(w);
+        w.u8(self.x as u8);
+        w.bool(self.w == WriteLatch::Step1);
+        w.usize(self.frames);
+        w.u16(self.line as u16);
+        w.u16(self.dot as u16);
+        w.bool(self.nmi);
+        self.rs.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        r.bytes(self.nametables.as_mut())?;
+        r.bytes(self.palettes.as_mut())?;
+        r.bytes(self.oam.as_mut())?;
+
+        self.ctrl.load_state(r)?;
+        self.mask.load_state(r)?;
+        self.status.load_state(r)?;
+        self.oam_addr = r.u8()? as usize;
+        self.data_buf = r.u8()?;
+
+        self.v.load_state(r)?;
+        self.t.load_state(r)?;
+        self.x = r.u8()? as usize & 0b0111;
+        self.w = if r.bool()? {
+            WriteLatch::Step1
+        } else {
+            WriteLatch::Step0
+        };
+        self.frames = r.usize()?;
+        self.line = r.u16()? as usize;
+        self.dot = r.u16()? as usize;
+        if self.line > 261 || self.dot > 340 {
+            return Err(StateError::Invalid("ppu timing"));
+        }
+        self.nmi = r.bool()?;
+        self.rs.load_state(r)
+    }
+}
+
 impl Ppu {
     fn reder_tile<T: IndexMut<usize, Output = u8>>(
         &self,
@@ -669,3 +810,53 @@ impl Ppu {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an iNES rom with 32 KB of zeroed prg and 8 KB of chr
+    fn cart(mapper: u8) -> Cartridge {
+        let mut data = vec![b'N', b'E', b'S', 0x1a, 2, 1, mapper << 4, mapper & 0xf0];
+        data.resize(16 + 0x8000 + 0x2000, 0);
+        Cartridge::load(&data).unwrap()
+    }
+
+    /// runs two frames with bg at $0000 and sprites at $1000, returns the line of the first irq
+    fn first_irq_line(cart: &mut Cartridge, mask: u8) -> Option<usize> {
+        let mut ppu = Ppu::default();
+        ppu.write(cart, 0x2000, 0x08);
+        ppu.write(cart, 0x2001, mask);
+        for dot in 0..262 * 341 * 2 {
+            ppu.tick(cart);
+            if dot % 3 == 0 {
+                cart.tick();
+            }
+            if cart.poll_irq() {
+                return Some(ppu.timing().0);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_mmc3_irq_without_sprites() {
+        for mask in [0x18, 0x08] {
+            let mut cart = cart(4);
+            cart.write(0xc000, 40);
+            cart.write(0xc001, 0);
+            cart.write(0xe001, 0);
+            assert_eq!(first_irq_line(&mut cart, mask), Some(40));
+        }
+    }
+
+    #[test]
+    fn test_mmc5_irq_without_sprites() {
+        for mask in [0x18, 0x08] {
+            let mut cart = cart(5);
+            cart.write(0x5203, 40);
+            cart.write(0x5204, 0x80);
+            assert_eq!(first_irq_line(&mut cart, mask), Some(41));
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::state::{Snapshot, StateReader, StateResult, StateWriter};
 use bit_field::BitField;
 
 /// PPU control register
@@ -241,3 +242,46 @@ impl ShiftReg {
         self.1.set_bits(0..8, b1.reverse_bits() as u16);
     }
 }
+
+macro_rules! snapshot_u8_reg {
+    ($($reg:ty),*) => {
+        $(
+            impl Snapshot for $reg {
+                fn save_state(&self, w: &mut StateWriter) {
+                    w.u8(self.0);
+                }
+
+                fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+                    self.0 = r.u8()?;
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+snapshot_u8_reg!(PpuCtrl, PpuMask, PpuStatus);
+
+impl Snapshot for VramAddr {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.0);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.0 = r.u16()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for ShiftReg {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.0);
+        w.u16(self.1);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()> {
+        self.0 = r.u16()?;
+        self.1 = r.u16()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+//! save states
+//!
+//! layout: magic, format version, rom hash, then every component in a fixed order.
+
+use std::fmt;
+
+const MAGIC: [u8; 4] = *b"LESS";
+
+/// bumped whenever the layout of any component changes
+pub const STATE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    Version(u32),
+    RomMismatch,
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::Version(v) => write!(
+                f,
+                "save state version {} is not supported (expected {})",
+                v, STATE_VERSION
+            ),
+            StateError::RomMismatch => write!(f, "save state belongs to another rom"),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid(what) => write!(f, "save state has invalid {}", what),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+pub type StateResult<T> = Result<T, StateError>;
+
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new(rom_hash: u32) -> Self {
+        let mut w = Self::default();
+        w.bytes(&MAGIC);
+        w.u32(STATE_VERSION);
+        w.u32(rom_hash);
+        w
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, v: usize) {
+        self.u64(v as u64);
+    }
+
+    pub fn f32(&mut self, v: f32) {
+        self.u32(v.to_bits());
+    }
+
+    /// raw bytes, the reader must know the length
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+
+    /// length prefixed bytes
+    pub fn block(&mut self, v: &[u8]) {
+        self.u32(v.len() as u32);
+        self.bytes(v);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(data: &'a [u8], rom_hash: u32) -> StateResult<Self> {
+        let mut r = Self { data };
+
+        let mut magic = [0u8; 4];
+        r.bytes(&mut magic).map_err(|_| StateError::BadMagic)?;
+        if magic != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+
+        let version = r.u32()?;
+        if version != STATE_VERSION {
+            return Err(StateError::Version(version));
+        }
+        if r.u32()? != rom_hash {
+            return Err(StateError::RomMismatch);
+        }
+
+        Ok(r)
+    }
+
+    fn take(&mut self, n: usize) -> StateResult<&'a [u8]> {
+        if self.data.len() < n {
+            return Err(StateError::Truncated);
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> StateResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> StateResult<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("bool")),
+        }
+    }
+
+    pub fn u16(&mut self) -> StateResult<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> StateResult<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> StateResult<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn usize(&mut self) -> StateResult<usize> {
+        usize::try_from(self.u64()?).map_err(|_| StateError::Invalid("usize"))
+    }
+
+    pub fn f32(&mut self) -> StateResult<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    pub fn bytes(&mut self, buf: &mut [u8]) -> StateResult<()> {
+        buf.copy_from_slice(self.take(buf.len())?);
+        Ok(())
+    }
+
+    /// length prefixed bytes, the length must match `buf`
+    pub fn block(&mut self, buf: &mut [u8]) -> StateResult<()> {
+        if self.u32()? as usize != buf.len() {
+            return Err(StateError::Invalid("block size"));
+        }
+        self.bytes(buf)
+    }
+
+    pub(crate) fn finish(self) -> StateResult<()> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(StateError::Invalid("trailing data"))
+        }
+    }
+}
+
+/// components that can be written to and restored from a save state
+pub(crate) trait Snapshot {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> StateResult<()>;
+}
+
+/// crc32 (ieee), used to tie save states to a rom
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Bus, Cartridge, Cpu};
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_header() {
+        let mut w = StateWriter::new(0x1234);
+        w.u16(0xbeef);
+        let data = w.finish();
+
+        let mut r = StateReader::new(&data, 0x1234).unwrap();
+        assert_eq!(r.u16(), Ok(0xbeef));
+        assert_eq!(r.u8(), Err(StateError::Truncated));
+
+        assert_eq!(
+            StateReader::new(&data, 0x4321).err(),
+            Some(StateError::RomMismatch)
+        );
+        assert_eq!(
+            StateReader::new(&data[1..], 0x1234).err(),
+            Some(StateError::BadMagic)
+        );
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut bus = Bus::new(Cartridge::empty());
+        let mut cpu = Cpu::default();
+        bus.reset(&mut cpu);
+
+        (0..1000).for_each(|_| bus.exec(&mut cpu));
+        let state = bus.save_state(&cpu);
+
+        (0..1000).for_each(|_| bus.exec(&mut cpu));
+        assert_ne!(bus.save_state(&cpu), state);
+
+        bus.load_state(&mut cpu, &state).unwrap();
+        assert_eq!(bus.save_state(&cpu), state);
+
+        (0..1000).for_each(|_| bus.exec(&mut cpu));
+        let state2 = bus.save_state(&cpu);
+        assert_eq!(
+            bus.load_state(&mut cpu, &state[..state.len() - 1]),
+            Err(StateError::Truncated)
+        );
+        assert_eq!(bus.save_state(&cpu), state2);
+    }
+}
@@ -0,0 +1,170 @@
+//! execution trace logging
+//!
+//! one line per instruction in the format of `Cpu::dump`, so traces can be diffed
+//! against other emulators.
+
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+
+use crate::{Bus, Cpu};
+
+/// size of the prg banks `TraceFilter::prg_bank` counts in
+pub const TRACE_BANK_SIZE: usize = 0x2000;
+
+/// which instructions get logged, everything by default
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// only log while the pc is in this range
+    pub range: Option<RangeInclusive<u16>>,
+    /// only log inside the nmi handler
+    pub nmi_only: bool,
+    /// only log while the pc is in this 8 KB bank of prg rom
+    pub prg_bank: Option<usize>,
+}
+
+pub struct Tracer {
+    out: BufWriter<Box<dyn Write + Send>>,
+    filter: TraceFilter,
+    /// stop once this many bytes are written
+    limit: Option<usize>,
+    written: usize,
+    line: Vec<u8>,
+    /// stack pointer on entering the nmi handler, cleared once it returns
+    nmi_sp: Option<u8>,
+    done: bool,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(
+        out: impl Write + Send + 'static,
+        filter: TraceFilter,
+        limit: Option<usize>,
+    ) -> Self {
+        Self {
+            out: BufWriter::new(Box::new(out)),
+            filter,
+            limit,
+            written: 0,
+            line: Vec::new(),
+            nmi_sp: None,
+            done: false,
+            error: None,
+        }
+    }
+
+    /// bytes written so far
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    /// the size limit was reached or writing failed
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    /// flush the output, returns the bytes written or the error that ended the trace
+    pub fn finish(mut self) -> io::Result<usize> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()?;
+        Ok(self.written)
+    }
+
+    /// log the instruction at the pc if it passes the filter
+    pub(crate) fn trace(&mut self, bus: &Bus, cpu: &Cpu) {
+        if self.done {
+            return;
+        }
+
+        let s = cpu.status();
+        if self.nmi_sp.is_some_and(|sp| s.sp > sp) {
+            self.nmi_sp = None;
+        }
+        if !self.accepts(bus, s.pc) {
+            return;
+        }
+
+        self.line.clear();
+        let res = cpu
+            .dump(bus, &mut self.line)
+            .and_then(|_| self.out.write_all(&self.line));
+        match res {
+            Ok(()) => {
+                self.written += self.line.len();
+                self.done = self.limit.is_some_and(|l| self.written >= l);
+            }
+            Err(e) => {
+                self.error = Some(e);
+                self.done = true;
+            }
+        }
+    }
+
+    pub(crate) fn on_nmi(&mut self, sp: u8) {
+        if self.nmi_sp.is_none() {
+            self.nmi_sp = Some(sp);
+        }
+    }
+
+    fn accepts(&self, bus: &Bus, pc: u16) -> bool {
+        let f = &self.filter;
+        (!f.nmi_only || self.nmi_sp.is_some())
+            && f.range.as_ref().is_none_or(|r| r.contains(&pc))
+            && f.prg_bank.is_none_or(|bank| {
+                bus.cart()
+                    .prg_offset(pc)
+                    .is_some_and(|o| o / TRACE_BANK_SIZE == bank)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cartridge;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut bus = Bus::new(Cartridge::empty());
+        let mut cpu = Cpu::default();
+        bus.reset(&mut cpu);
+
+        let buf = SharedBuf::default();
+        bus.start_trace(Tracer::new(buf.clone(), TraceFilter::default(), Some(100)));
+        for _ in 0..3 {
+            bus.exec(&mut cpu);
+        }
+        assert!(bus.tracer().is_some_and(|t| t.done()));
+        let written = bus.stop_trace().unwrap().unwrap();
+
+        let text = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(written, text.len());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("FF00  4C 00 FF  JMP $FF00      A:00 X:00 Y:00"));
+
+        let filter = TraceFilter {
+            range: Some(0x8000..=0xbfff),
+            ..Default::default()
+        };
+        bus.start_trace(Tracer::new(buf.clone(), filter, None));
+        bus.exec(&mut cpu);
+        assert_eq!(bus.stop_trace().unwrap().unwrap(), 0);
+    }
+}